@@ -0,0 +1,54 @@
+//! The whole analysis of the small repository bundled in `fixture.bundle`, with an empty and with
+//! a warm cache
+//!
+//! The bundle is cloned with `git`, which has to be on the `PATH`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use regex::Regex;
+use std::sync::atomic::AtomicBool;
+use unsafe_track::{analyse_repo, AnalysisCache, AnalysisLimits, PathFilter};
+
+mod fixture;
+
+fn path_filter() -> PathFilter {
+    PathFilter {
+        include: Regex::new(r"\.rs$").unwrap(),
+        exclude: None,
+        use_ignore_file: true,
+        skip_vendored: false,
+        subdir: None,
+        expand_local_macros: false,
+    }
+}
+
+fn analysis(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let url = fixture::clone_fixture(dir.path(), "fixture.git");
+    let url = url.to_str().unwrap();
+    let limits = AnalysisLimits::default();
+    let should_interrupt = AtomicBool::new(false);
+    let analyse = |cache: &AnalysisCache| {
+        analyse_repo(
+            cache,
+            url,
+            "HEAD",
+            path_filter(),
+            &limits,
+            &should_interrupt,
+        )
+        .unwrap()
+    };
+
+    let mut group = c.benchmark_group("analyse_repo");
+    group.sample_size(20);
+    group.bench_function("cold", |b| {
+        b.iter_with_setup(|| AnalysisCache::new(16 << 20), |cache| analyse(&cache))
+    });
+    let cache = AnalysisCache::new(16 << 20);
+    analyse(&cache);
+    group.bench_function("warm", |b| b.iter(|| analyse(&cache)));
+    group.finish();
+}
+
+criterion_group!(benches, analysis);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! The small repository bundled in `fixture.bundle`, shared by the benchmark and the tests
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Clones the bundle to `name` in `dir`, with `git`, which has to be on the `PATH`
+pub fn clone_fixture(dir: &Path, name: &str) -> PathBuf {
+    let bundle = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/fixture.bundle");
+    let repo = dir.join(name);
+    let status = Command::new("git")
+        .args(["clone", "--quiet", "--bare"])
+        .arg(&bundle)
+        .arg(&repo)
+        .status()
+        .expect("Failed to run git");
+    assert!(status.success(), "Failed to clone {}", bundle.display());
+    repo
+}
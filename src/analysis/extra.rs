@@ -0,0 +1,68 @@
+//! Counts of the kinds of unsafe usage that geiger doesn't tell apart
+
+use serde::Serialize;
+use std::ops::AddAssign;
+use syn::visit::{self, Visit};
+
+/// Unsafe usages of a file (or a whole commit) broken down by their kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExtraMetrics {
+    /// Functions declared in `extern` blocks, which is how FFI is usually done
+    pub ffi_functions: u64,
+    /// `unsafe { ... }` blocks
+    pub unsafe_blocks: u64,
+    /// `unsafe fn` definitions, including the methods and the trait items with a default body
+    pub unsafe_functions: u64,
+    /// `unsafe impl`s
+    pub unsafe_impls: u64,
+}
+
+impl AddAssign for ExtraMetrics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.ffi_functions += rhs.ffi_functions;
+        self.unsafe_blocks += rhs.unsafe_blocks;
+        self.unsafe_functions += rhs.unsafe_functions;
+        self.unsafe_impls += rhs.unsafe_impls;
+    }
+}
+
+impl<'ast> Visit<'ast> for ExtraMetrics {
+    fn visit_foreign_item_fn(&mut self, item: &'ast syn::ForeignItemFn) {
+        self.ffi_functions += 1;
+        visit::visit_foreign_item_fn(self, item);
+    }
+
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        self.unsafe_blocks += 1;
+        visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.unsafe_functions += item.sig.unsafety.is_some() as u64;
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_method(&mut self, item: &'ast syn::ImplItemMethod) {
+        self.unsafe_functions += item.sig.unsafety.is_some() as u64;
+        visit::visit_impl_item_method(self, item);
+    }
+
+    fn visit_trait_item_method(&mut self, item: &'ast syn::TraitItemMethod) {
+        // a declaration without a body is not an unsafe usage by itself
+        self.unsafe_functions += (item.sig.unsafety.is_some() && item.default.is_some()) as u64;
+        visit::visit_trait_item_method(self, item);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        self.unsafe_impls += item.unsafety.is_some() as u64;
+        visit::visit_item_impl(self, item);
+    }
+}
+
+/// Counts the unsafe usages of a Rust source file, including the tests like geiger does
+pub(crate) fn find_extra_metrics(src: &str) -> Result<ExtraMetrics, syn::Error> {
+    let file = syn::parse_file(src)?;
+    let mut metrics = ExtraMetrics::default();
+    metrics.visit_file(&file);
+    Ok(metrics)
+}
@@ -0,0 +1,593 @@
+//! An approximate expansion of the `macro_rules!` macros invoked in the file they are defined in
+//!
+//! geiger only sees the source as written, so the `unsafe` code a local macro expands to isn't
+//! counted. The expansion here covers the common cases and nothing more: the first rule matching
+//! the whole input is used, fragments are matched by parsing a prefix of the input with syn, without
+//! backtracking, and the expanded code is not expanded again. Macros of other files and crates are
+//! left as they are.
+
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::visit::{self, Visit};
+use syn::visit_mut::{self, VisitMut};
+use thiserror::Error;
+
+/// The files whose expansions add up to more tokens than this are analysed as written
+const MAX_EXPANDED_TOKENS: usize = 100_000;
+
+#[derive(Error, Debug)]
+pub(crate) enum ExpansionError {
+    #[error("failed to parse the file: {0}")]
+    Parse(#[from] syn::Error),
+    #[error("the rules of `{0}!` use unsupported syntax")]
+    UnsupportedRules(String),
+    #[error("no rule of `{0}!` matches an invocation")]
+    NoMatchingRule(String),
+    #[error("the expansion of `{0}!` repeats variables with different numbers of values")]
+    RepetitionMismatch(String),
+    #[error("the expansion of `{0}!` doesn't parse: {1}")]
+    InvalidExpansion(String, syn::Error),
+    #[error("the expansions are longer than {MAX_EXPANDED_TOKENS} tokens")]
+    TooLarge,
+}
+
+/// Expands the invocations of the local `macro_rules!` macros of a file
+///
+/// Returns `None` if the file invokes none of them, so it can be analysed as it is.
+pub(crate) fn expand_local_macros(source: &str) -> Result<Option<String>, ExpansionError> {
+    let mut file = syn::parse_file(source)?;
+
+    let mut definitions = Definitions::default();
+    definitions.visit_file(&file);
+    if definitions.0.is_empty() {
+        return Ok(None);
+    }
+
+    let mut expander = Expander {
+        macros: &definitions.0,
+        expanded_tokens: 0,
+        expansions: 0,
+        error: None,
+    };
+    expander.visit_file_mut(&mut file);
+    if let Some(error) = expander.error {
+        return Err(error);
+    }
+
+    Ok((expander.expansions > 0).then(|| file.to_token_stream().to_string()))
+}
+
+/// A piece of the left-hand side of a rule
+enum Matcher {
+    Token(TokenTree),
+    Group(Delimiter, Vec<Matcher>),
+    Fragment {
+        name: String,
+        kind: String,
+    },
+    Repetition {
+        matchers: Vec<Matcher>,
+        separator: Option<TokenTree>,
+        op: char,
+    },
+}
+
+/// A piece of the right-hand side of a rule
+enum Transcriber {
+    Token(TokenTree),
+    Group(Delimiter, Vec<Transcriber>),
+    Variable(String),
+    Repetition {
+        transcribers: Vec<Transcriber>,
+        separator: Option<TokenTree>,
+    },
+}
+
+struct Rule {
+    matchers: Vec<Matcher>,
+    transcribers: Vec<Transcriber>,
+}
+
+/// What a fragment variable matched, nested in one sequence per repetition it is in
+#[derive(Clone)]
+enum Binding {
+    Tokens(Vec<TokenTree>),
+    Sequence(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+/// The `macro_rules!` definitions of a file, `None` for the ones with unsupported rules
+#[derive(Default)]
+struct Definitions(HashMap<String, Option<Vec<Rule>>>);
+
+impl<'ast> Visit<'ast> for Definitions {
+    fn visit_item_macro(&mut self, item: &'ast syn::ItemMacro) {
+        if let Some(ident) = &item.ident {
+            if item.mac.path.is_ident("macro_rules") {
+                // with several definitions of the same name, like `#[cfg]` variants, the last wins
+                self.0
+                    .insert(ident.to_string(), parse_rules(item.mac.tokens.clone()));
+            }
+        }
+        visit::visit_item_macro(self, item);
+    }
+}
+
+fn is_punct(token: Option<&TokenTree>, ch: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(p)) if p.as_char() == ch)
+}
+
+/// Compares two tokens, ignoring their spans and how punctuation is joined
+fn same_token(a: &TokenTree, b: &TokenTree) -> bool {
+    match (a, b) {
+        (TokenTree::Ident(a), TokenTree::Ident(b)) => a == b,
+        (TokenTree::Punct(a), TokenTree::Punct(b)) => a.as_char() == b.as_char(),
+        (TokenTree::Literal(a), TokenTree::Literal(b)) => a.to_string() == b.to_string(),
+        _ => false,
+    }
+}
+
+fn parse_rules(tokens: TokenStream) -> Option<Vec<Rule>> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut rules = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let (TokenTree::Group(matcher), Some(TokenTree::Group(transcriber))) =
+            (&tokens[i], tokens.get(i + 3))
+        else {
+            return None;
+        };
+        if !is_punct(tokens.get(i + 1), '=') || !is_punct(tokens.get(i + 2), '>') {
+            return None;
+        }
+        rules.push(Rule {
+            matchers: parse_matchers(matcher.stream())?,
+            transcribers: parse_transcribers(transcriber.stream())?,
+        });
+        i += 4;
+        if is_punct(tokens.get(i), ';') {
+            i += 1;
+        }
+    }
+    Some(rules)
+}
+
+/// Parses the separator and the operator after `$(...)`, returns them with the number of tokens
+fn parse_repetition_op(tokens: &[TokenTree]) -> Option<(Option<TokenTree>, char, usize)> {
+    let op = |token: Option<&TokenTree>| match token {
+        Some(TokenTree::Punct(p)) if matches!(p.as_char(), '*' | '+' | '?') => Some(p.as_char()),
+        _ => None,
+    };
+    match op(tokens.first()) {
+        Some(op) => Some((None, op, 1)),
+        None => Some((Some(tokens.first()?.clone()), op(tokens.get(1))?, 2)),
+    }
+}
+
+fn parse_matchers(tokens: TokenStream) -> Option<Vec<Matcher>> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut matchers = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (TokenTree::Punct(p), Some(TokenTree::Ident(name))) if p.as_char() == '$' => {
+                let Some(TokenTree::Ident(kind)) = tokens.get(i + 3) else {
+                    return None;
+                };
+                if !is_punct(tokens.get(i + 2), ':') {
+                    return None;
+                }
+                matchers.push(Matcher::Fragment {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                });
+                i += 4;
+            }
+            (TokenTree::Punct(p), Some(TokenTree::Group(group)))
+                if p.as_char() == '$' && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                let (separator, op, len) = parse_repetition_op(&tokens[i + 2..])?;
+                matchers.push(Matcher::Repetition {
+                    matchers: parse_matchers(group.stream())?,
+                    separator,
+                    op,
+                });
+                i += 2 + len;
+            }
+            (TokenTree::Group(group), _) => {
+                matchers.push(Matcher::Group(
+                    group.delimiter(),
+                    parse_matchers(group.stream())?,
+                ));
+                i += 1;
+            }
+            (token, _) => {
+                matchers.push(Matcher::Token(token.clone()));
+                i += 1;
+            }
+        }
+    }
+    Some(matchers)
+}
+
+fn parse_transcribers(tokens: TokenStream) -> Option<Vec<Transcriber>> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut transcribers = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (TokenTree::Punct(p), Some(TokenTree::Ident(name))) if p.as_char() == '$' => {
+                transcribers.push(if name == "crate" {
+                    Transcriber::Token(TokenTree::Ident(Ident::new("crate", name.span())))
+                } else {
+                    Transcriber::Variable(name.to_string())
+                });
+                i += 2;
+            }
+            (TokenTree::Punct(p), Some(TokenTree::Group(group)))
+                if p.as_char() == '$' && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                let (separator, _op, len) = parse_repetition_op(&tokens[i + 2..])?;
+                transcribers.push(Transcriber::Repetition {
+                    transcribers: parse_transcribers(group.stream())?,
+                    separator,
+                });
+                i += 2 + len;
+            }
+            (TokenTree::Group(group), _) => {
+                transcribers.push(Transcriber::Group(
+                    group.delimiter(),
+                    parse_transcribers(group.stream())?,
+                ));
+                i += 1;
+            }
+            (token, _) => {
+                transcribers.push(Transcriber::Token(token.clone()));
+                i += 1;
+            }
+        }
+    }
+    Some(transcribers)
+}
+
+/// Number of tokens a syn node parsed from the start of `input` took, `None` if it doesn't parse
+fn parsed_len<T: Parse>(input: &[TokenTree]) -> Option<usize> {
+    let parser = |stream: ParseStream| {
+        stream.parse::<T>()?;
+        let rest: TokenStream = stream.parse()?;
+        Ok(rest.into_iter().count())
+    };
+    let rest = parser.parse2(input.iter().cloned().collect()).ok()?;
+    input.len().checked_sub(rest)
+}
+
+/// Number of tokens a fragment of the given kind takes from the start of `input`
+fn match_fragment(kind: &str, input: &[TokenTree]) -> Option<usize> {
+    match kind {
+        "tt" => input.first().map(|_| 1),
+        "ident" => matches!(input.first(), Some(TokenTree::Ident(_))).then_some(1),
+        "lifetime" => (is_punct(input.first(), '\'')
+            && matches!(input.get(1), Some(TokenTree::Ident(_))))
+        .then_some(2),
+        "literal" => match input {
+            [TokenTree::Literal(_), ..] => Some(1),
+            [TokenTree::Punct(p), TokenTree::Literal(_), ..] if p.as_char() == '-' => Some(2),
+            _ => None,
+        },
+        "vis" => parsed_len::<syn::Visibility>(input),
+        "expr" | "expr_2021" => parsed_len::<syn::Expr>(input),
+        "ty" => parsed_len::<syn::Type>(input),
+        "path" => parsed_len::<syn::Path>(input),
+        "pat" | "pat_param" => parsed_len::<syn::Pat>(input),
+        "stmt" => parsed_len::<syn::Stmt>(input),
+        "block" => parsed_len::<syn::Block>(input),
+        "item" => parsed_len::<syn::Item>(input),
+        "meta" => parsed_len::<syn::Meta>(input),
+        _ => None,
+    }
+}
+
+fn matcher_variables<'a>(matchers: &'a [Matcher], names: &mut Vec<&'a str>) {
+    for matcher in matchers {
+        match matcher {
+            Matcher::Token(_) => {}
+            Matcher::Group(_, matchers) | Matcher::Repetition { matchers, .. } => {
+                matcher_variables(matchers, names)
+            }
+            Matcher::Fragment { name, .. } => names.push(name),
+        }
+    }
+}
+
+/// Matches the start of `input`, returns the number of tokens taken and the variables bound
+fn match_matchers(matchers: &[Matcher], input: &[TokenTree]) -> Option<(usize, Bindings)> {
+    let mut pos = 0;
+    let mut bindings = Bindings::new();
+    for matcher in matchers {
+        match matcher {
+            Matcher::Token(token) => {
+                if !input.get(pos).is_some_and(|t| same_token(t, token)) {
+                    return None;
+                }
+                pos += 1;
+            }
+            Matcher::Group(delimiter, matchers) => {
+                let Some(TokenTree::Group(group)) = input.get(pos) else {
+                    return None;
+                };
+                if group.delimiter() != *delimiter {
+                    return None;
+                }
+                let tokens = group.stream().into_iter().collect::<Vec<_>>();
+                let (len, inner) = match_matchers(matchers, &tokens)?;
+                if len != tokens.len() {
+                    return None;
+                }
+                bindings.extend(inner);
+                pos += 1;
+            }
+            Matcher::Fragment { name, kind } => {
+                let len = match_fragment(kind, &input[pos..])?;
+                bindings.insert(
+                    name.clone(),
+                    Binding::Tokens(input[pos..pos + len].to_vec()),
+                );
+                pos += len;
+            }
+            Matcher::Repetition {
+                matchers,
+                separator,
+                op,
+            } => {
+                let mut iterations = Vec::new();
+                loop {
+                    let mut next = pos;
+                    if let (Some(separator), false) = (separator, iterations.is_empty()) {
+                        if !input.get(next).is_some_and(|t| same_token(t, separator)) {
+                            break;
+                        }
+                        next += 1;
+                    }
+                    // an iteration taking nothing would repeat forever
+                    let Some((len, inner)) =
+                        match_matchers(matchers, &input[next..]).filter(|&(len, _)| len > 0)
+                    else {
+                        break;
+                    };
+                    iterations.push(inner);
+                    pos = next + len;
+                    if *op == '?' {
+                        break;
+                    }
+                }
+                if *op == '+' && iterations.is_empty() {
+                    return None;
+                }
+
+                let mut names = Vec::new();
+                matcher_variables(matchers, &mut names);
+                for name in names {
+                    let values = iterations
+                        .iter_mut()
+                        .filter_map(|inner| inner.remove(name))
+                        .collect();
+                    bindings.insert(name.to_string(), Binding::Sequence(values));
+                }
+            }
+        }
+    }
+    Some((pos, bindings))
+}
+
+fn transcriber_variables<'a>(transcribers: &'a [Transcriber], names: &mut Vec<&'a str>) {
+    for transcriber in transcribers {
+        match transcriber {
+            Transcriber::Token(_) => {}
+            Transcriber::Group(_, transcribers) | Transcriber::Repetition { transcribers, .. } => {
+                transcriber_variables(transcribers, names)
+            }
+            Transcriber::Variable(name) => names.push(name),
+        }
+    }
+}
+
+/// Substitutes the variables, `None` if the repetitions don't line up with the bound sequences
+fn transcribe(
+    transcribers: &[Transcriber],
+    bindings: &Bindings,
+    out: &mut Vec<TokenTree>,
+) -> Option<()> {
+    for transcriber in transcribers {
+        match transcriber {
+            Transcriber::Token(token) => out.push(token.clone()),
+            Transcriber::Group(delimiter, transcribers) => {
+                let mut inner = Vec::new();
+                transcribe(transcribers, bindings, &mut inner)?;
+                out.push(TokenTree::Group(Group::new(
+                    *delimiter,
+                    inner.into_iter().collect(),
+                )));
+            }
+            Transcriber::Variable(name) => match bindings.get(name) {
+                Some(Binding::Tokens(tokens)) => out.extend(tokens.iter().cloned()),
+                // still inside a repetition
+                Some(Binding::Sequence(_)) => return None,
+                // not a variable of the macro, left as written
+                None => {
+                    out.push(TokenTree::Punct(proc_macro2::Punct::new(
+                        '$',
+                        proc_macro2::Spacing::Alone,
+                    )));
+                    out.push(TokenTree::Ident(Ident::new(name, Span::call_site())));
+                }
+            },
+            Transcriber::Repetition {
+                transcribers,
+                separator,
+            } => {
+                let mut names = Vec::new();
+                transcriber_variables(transcribers, &mut names);
+                let sequences = names
+                    .into_iter()
+                    .filter_map(|name| match bindings.get(name) {
+                        Some(Binding::Sequence(values)) => Some((name, values)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let count = sequences.first()?.1.len();
+                if sequences.iter().any(|(_, values)| values.len() != count) {
+                    return None;
+                }
+
+                for i in 0..count {
+                    if let (Some(separator), true) = (separator, i > 0) {
+                        out.push(separator.clone());
+                    }
+                    let mut bindings = bindings.clone();
+                    for (name, values) in &sequences {
+                        bindings.insert(name.to_string(), values[i].clone());
+                    }
+                    transcribe(transcribers, &bindings, out)?;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+fn count_tokens(tokens: &TokenStream) -> usize {
+    tokens
+        .clone()
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Group(group) => 1 + count_tokens(&group.stream()),
+            _ => 1,
+        })
+        .sum()
+}
+
+struct Expander<'a> {
+    macros: &'a HashMap<String, Option<Vec<Rule>>>,
+    expanded_tokens: usize,
+    expansions: usize,
+    /// The first failure, the rest of the file is left as it is after it
+    error: Option<ExpansionError>,
+}
+
+impl Expander<'_> {
+    /// Expands an invocation and parses it as `T`, `None` if it isn't one of a local macro or fails
+    fn expand<T: Parse>(&mut self, mac: &syn::Macro, wrap: Delimiter) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+        let name = mac.path.get_ident()?.to_string();
+        let rules = self.macros.get(&name)?;
+
+        let result = self.expand_rules(&name, rules.as_deref(), mac.tokens.clone());
+        match result.and_then(|tokens| {
+            let tokens = match wrap {
+                Delimiter::None => tokens,
+                _ => TokenTree::Group(Group::new(wrap, tokens)).into(),
+            };
+            syn::parse2(tokens).map_err(|e| ExpansionError::InvalidExpansion(name, e))
+        }) {
+            Ok(expanded) => {
+                self.expansions += 1;
+                Some(expanded)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn expand_rules(
+        &mut self,
+        name: &str,
+        rules: Option<&[Rule]>,
+        input: TokenStream,
+    ) -> Result<TokenStream, ExpansionError> {
+        let rules = rules.ok_or_else(|| ExpansionError::UnsupportedRules(name.to_string()))?;
+        let input = input.into_iter().collect::<Vec<_>>();
+        let (rule, bindings) = rules
+            .iter()
+            .find_map(|rule| match match_matchers(&rule.matchers, &input) {
+                Some((len, bindings)) if len == input.len() => Some((rule, bindings)),
+                _ => None,
+            })
+            .ok_or_else(|| ExpansionError::NoMatchingRule(name.to_string()))?;
+
+        let mut out = Vec::new();
+        transcribe(&rule.transcribers, &bindings, &mut out)
+            .ok_or_else(|| ExpansionError::RepetitionMismatch(name.to_string()))?;
+        let tokens = out.into_iter().collect();
+
+        self.expanded_tokens += count_tokens(&tokens);
+        if self.expanded_tokens > MAX_EXPANDED_TOKENS {
+            return Err(ExpansionError::TooLarge);
+        }
+        Ok(tokens)
+    }
+
+    /// Replaces the item macros with the items they expand to
+    fn expand_items(&mut self, items: &mut Vec<syn::Item>) {
+        for mut item in std::mem::take(items) {
+            if let syn::Item::Macro(m) = &item {
+                if m.ident.is_none() {
+                    if let Some(file) = self.expand::<syn::File>(&m.mac, Delimiter::None) {
+                        items.extend(file.items);
+                        continue;
+                    }
+                }
+            }
+            self.visit_item_mut(&mut item);
+            items.push(item);
+        }
+    }
+}
+
+// the expanded code isn't visited, so nothing is expanded twice
+impl VisitMut for Expander<'_> {
+    fn visit_file_mut(&mut self, file: &mut syn::File) {
+        self.expand_items(&mut file.items);
+    }
+
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        if let Some((_, items)) = &mut item.content {
+            self.expand_items(items);
+        }
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        for mut stmt in std::mem::take(&mut block.stmts) {
+            if let syn::Stmt::Item(syn::Item::Macro(m)) = &stmt {
+                if m.ident.is_none() {
+                    if let Some(expanded) = self.expand::<syn::Block>(&m.mac, Delimiter::Brace) {
+                        block.stmts.extend(expanded.stmts);
+                        continue;
+                    }
+                }
+            }
+            self.visit_stmt_mut(&mut stmt);
+            block.stmts.push(stmt);
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Macro(m) = expr {
+            if let Some(block) = self.expand::<syn::Block>(&m.mac, Delimiter::Brace) {
+                *expr = syn::Expr::Block(syn::ExprBlock {
+                    attrs: m.attrs.clone(),
+                    label: None,
+                    block,
+                });
+                return;
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+}
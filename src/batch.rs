@@ -0,0 +1,162 @@
+//! Analysing the repositories listed in a file with `analyse --url-file`, one after the other, with
+//! their histories output as the series of a single chart or table
+
+use anyhow::Context;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use unsafe_track::analysis::{self, AnalysisCache, AnalysisLimits, PathFilter, RepoAnalysis};
+
+/// The per-file results kept between the repositories, so the files they share, like vendored
+/// dependencies or forked code, are analysed once
+const BATCH_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The URLs of a `--url-file`, one per line. The blank lines and the lines starting with `#` are
+/// left out.
+pub fn read_url_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let urls = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if urls.is_empty() {
+        anyhow::bail!("{} lists no repositories", path.display());
+    }
+    Ok(urls)
+}
+
+/// What every repository of the batch is analysed with
+pub struct Batch<'a> {
+    pub ref_name: &'a str,
+    pub path_filter: PathFilter,
+    pub limits: &'a AnalysisLimits,
+    pub trim_empty: bool,
+    /// Interrupts the analysis in progress, the repositories after it are left out
+    pub should_interrupt: &'a AtomicBool,
+}
+
+impl Batch<'_> {
+    /// Analyses the repositories in order, the failure of one is logged and doesn't stop the others
+    pub fn run(&self, urls: &[String]) -> Vec<Result<RepoAnalysis, String>> {
+        let cache = AnalysisCache::new(BATCH_CACHE_BYTES);
+        urls.iter()
+            .map(|url| {
+                if self.should_interrupt.load(Ordering::Relaxed) {
+                    return Err("interrupted".to_string());
+                }
+                let result = self.analyse(&cache, url);
+                if let Err(e) = &result {
+                    eprintln!("Analysis of {} failed: {}", url, e);
+                }
+                result
+            })
+            .collect()
+    }
+
+    fn analyse(&self, cache: &AnalysisCache, url: &str) -> Result<RepoAnalysis, String> {
+        let url = analysis::normalize_url(url).map_err(|e| e.to_string())?;
+        let mut analysis = analysis::analyse_repo(
+            cache,
+            &url,
+            self.ref_name,
+            self.path_filter.clone(),
+            self.limits,
+            self.should_interrupt,
+        )
+        .map_err(|e| e.to_string())?;
+        if analysis.match_stats.matched_blobs == 0 {
+            return Err("no files match the path filter".to_string());
+        }
+        if analysis.skipped_commits > 0 {
+            eprintln!(
+                "Skipped {} commits of {} that could not be parsed, the history can be incomplete",
+                analysis.skipped_commits, url
+            );
+        }
+        if self.trim_empty {
+            analysis.trim_empty();
+        }
+        Ok(analysis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn batch<'a>(limits: &'a AnalysisLimits, should_interrupt: &'a AtomicBool) -> Batch<'a> {
+        Batch {
+            ref_name: "HEAD",
+            path_filter: PathFilter {
+                include: Regex::new(r"\.rs$").unwrap(),
+                exclude: None,
+                use_ignore_file: true,
+                skip_vendored: false,
+                subdir: None,
+                expand_local_macros: false,
+            },
+            limits,
+            trim_empty: false,
+            should_interrupt,
+        }
+    }
+
+    #[test]
+    fn url_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("urls");
+        std::fs::write(
+            &path,
+            "# the first one\n  owner/repo  \n\n\t\n# owner/commented\nhttps://example.com/other\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_url_file(&path).unwrap(),
+            ["owner/repo", "https://example.com/other"]
+        );
+
+        std::fs::write(&path, "\n# nothing yet\n   \n").unwrap();
+        let e = read_url_file(&path).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("{} lists no repositories", path.display())
+        );
+
+        let e = read_url_file(&dir.path().join("missing")).unwrap_err();
+        assert!(e.to_string().starts_with("Failed to read"), "{}", e);
+    }
+
+    #[test]
+    fn failure_doesnt_stop_the_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = crate::fixture::clone_fixture(dir.path(), "fixture.git");
+        let urls = [
+            format!("file://{}", dir.path().join("missing.git").display()),
+            format!("file://{}", fixture.display()),
+        ];
+        let limits = AnalysisLimits::default();
+        let should_interrupt = AtomicBool::new(false);
+        let analyses = batch(&limits, &should_interrupt).run(&urls);
+
+        assert_eq!(analyses.len(), 2);
+        assert!(analyses[0].is_err());
+        let analysis = analyses[1].as_ref().unwrap();
+        assert!(!analysis.commits.is_empty());
+    }
+
+    #[test]
+    fn interrupted_batch() {
+        let limits = AnalysisLimits::default();
+        let should_interrupt = AtomicBool::new(true);
+        let urls = ["owner/repo".to_string(), "owner/other".to_string()];
+        let analyses = batch(&limits, &should_interrupt).run(&urls);
+        let errors = analyses
+            .into_iter()
+            .map(Result::unwrap_err)
+            .collect::<Vec<_>>();
+        assert_eq!(errors, ["interrupted", "interrupted"]);
+    }
+}
@@ -0,0 +1,321 @@
+//! Named presets of the filters and the chart options, read from a TOML file like
+//!
+//! ```toml
+//! [presets.opus]
+//! filter = '(celt|silk)/.*\.rs$'
+//! exclude = 'tests/'
+//! x-coord = "date"
+//! y-coord = ["functions", "expressions"]
+//! count-kinds = ["functions", "methods", "item-impls"]
+//! palette = "cud"
+//! width = 1200
+//! height = 600
+//! ```
+
+use anyhow::Context;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use unsafe_track::plot::palette::Palette;
+use unsafe_track::plot::{CountKind, XCoord, YCoord, IMAGE_SIZE_RANGE};
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    presets: BTreeMap<String, Preset>,
+}
+
+/// Defaults for the options of the same names, the ones given explicitly still win
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preset {
+    pub filter: Option<String>,
+    pub exclude: Option<String>,
+    #[serde(default, deserialize_with = "value_enum")]
+    pub x_coord: Option<XCoord>,
+    #[serde(default, deserialize_with = "value_enums")]
+    pub y_coord: Option<Vec<YCoord>>,
+    #[serde(default, deserialize_with = "value_enums")]
+    pub count_kinds: Option<Vec<CountKind>>,
+    #[serde(default, deserialize_with = "value_enum")]
+    pub palette: Option<Palette>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// The options a [`Preset`] gives the defaults of, wherever the command line or the query holds
+/// them
+pub struct PresetOptions<'a> {
+    pub filter: &'a mut Option<String>,
+    pub exclude: &'a mut Option<String>,
+    pub x_coord: &'a mut XCoord,
+    pub y_coord: &'a mut Vec<YCoord>,
+    pub count_kinds: &'a mut Vec<CountKind>,
+    pub palette: &'a mut Palette,
+    pub width: &'a mut Option<u32>,
+    pub height: &'a mut Option<u32>,
+}
+
+impl Preset {
+    /// Sets the `options` that weren't `given` explicitly to the values of the preset, `given` is
+    /// asked with the names of the fields of [`PresetOptions`]
+    pub fn apply(&self, options: PresetOptions<'_>, given: impl Fn(&str) -> bool) {
+        fn set<T>(option: &mut T, value: Option<T>, given: bool) {
+            if let Some(value) = value.filter(|_| !given) {
+                *option = value;
+            }
+        }
+        set(
+            options.filter,
+            self.filter.clone().map(Some),
+            given("filter"),
+        );
+        set(
+            options.exclude,
+            self.exclude.clone().map(Some),
+            given("exclude"),
+        );
+        set(options.x_coord, self.x_coord, given("x_coord"));
+        set(options.y_coord, self.y_coord.clone(), given("y_coord"));
+        set(
+            options.count_kinds,
+            self.count_kinds.clone(),
+            given("count_kinds"),
+        );
+        set(options.palette, self.palette, given("palette"));
+        set(options.width, self.width.map(Some), given("width"));
+        set(options.height, self.height.map(Some), given("height"));
+    }
+}
+
+/// `~/.config/unsafe-track/config.toml`, or in `$XDG_CONFIG_HOME` if it's set
+fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("unsafe-track").join("config.toml"))
+}
+
+impl Config {
+    /// Reads the config at `path`, or at the default path if there is a file there
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        // a typo in a regex is reported right away, not when the preset is used
+        for (name, preset) in &config.presets {
+            for regex in preset.filter.iter().chain(&preset.exclude) {
+                Regex::new(regex).with_context(|| {
+                    format!(
+                        "Invalid regex in the preset `{}` of {}",
+                        name,
+                        path.display()
+                    )
+                })?;
+            }
+            for size in preset.width.iter().chain(&preset.height) {
+                if !IMAGE_SIZE_RANGE.contains(size) {
+                    anyhow::bail!(
+                        "The size {} of the preset `{}` of {} isn't between {} and {}",
+                        size,
+                        name,
+                        path.display(),
+                        IMAGE_SIZE_RANGE.start(),
+                        IMAGE_SIZE_RANGE.end()
+                    );
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// The preset called `name`, the error lists the ones there are
+    pub fn preset(&self, name: &str) -> Result<&Preset, String> {
+        self.presets.get(name).ok_or_else(|| {
+            if self.presets.is_empty() {
+                format!("Unknown preset `{}`, no presets are configured", name)
+            } else {
+                let names = self.presets.keys().map(String::as_str);
+                format!(
+                    "Unknown preset `{}`, the available ones are: {}",
+                    name,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            }
+        })
+    }
+}
+
+/// Spelled like the values of the command-line options, in any case
+fn value_enum<'de, D: Deserializer<'de>, T: ValueEnum>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    T::from_str(&value, true)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// A non-empty list of [`value_enum`]s, the options taking several of them need at least one
+fn value_enums<'de, D: Deserializer<'de>, T: ValueEnum>(
+    deserializer: D,
+) -> Result<Option<Vec<T>>, D::Error> {
+    let values = Vec::<String>::deserialize(deserializer)?;
+    if values.is_empty() {
+        return Err(serde::de::Error::invalid_length(0, &"at least one value"));
+    }
+    values
+        .iter()
+        .map(|value| T::from_str(value, true).map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(dir: &Path, text: &str) -> PathBuf {
+        let path = dir.join("config.toml");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn module_doc_example() {
+        let doc = include_str!("config.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("//!"))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect::<Vec<_>>();
+        let start = doc.iter().position(|line| *line == "```toml").unwrap() + 1;
+        let end = start + doc[start..].iter().position(|line| *line == "```").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), &doc[start..end].join("\n"));
+
+        let config = Config::load(Some(&path)).unwrap();
+        let preset = config.preset("opus").unwrap();
+        assert_eq!(preset.filter.as_deref(), Some(r"(celt|silk)/.*\.rs$"));
+        assert_eq!(preset.exclude.as_deref(), Some("tests/"));
+        assert!(matches!(preset.x_coord, Some(XCoord::Date)));
+        assert_eq!(
+            preset.y_coord,
+            Some(vec![YCoord::Functions, YCoord::Expressions])
+        );
+        assert_eq!(
+            preset.count_kinds,
+            Some(vec![
+                CountKind::Functions,
+                CountKind::Methods,
+                CountKind::ItemImpls
+            ])
+        );
+        assert_eq!(preset.palette, Some(Palette::Cud));
+        assert_eq!((preset.width, preset.height), (Some(1200), Some(600)));
+    }
+
+    #[test]
+    fn unknown_preset() {
+        let e = Config::default().preset("opus").unwrap_err();
+        assert_eq!(e, "Unknown preset `opus`, no presets are configured");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(
+            dir.path(),
+            "[presets.opus]\nfilter = 'celt/'\n[presets.dav1d]\nfilter = 'src/'\n",
+        );
+        let config = Config::load(Some(&path)).unwrap();
+        let e = config.preset("rav1e").unwrap_err();
+        assert_eq!(
+            e,
+            "Unknown preset `rav1e`, the available ones are: dav1d, opus"
+        );
+    }
+
+    #[test]
+    fn empty_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        for option in ["y-coord", "count-kinds"] {
+            let path = write_config(dir.path(), &format!("[presets.p]\n{} = []\n", option));
+            let e = Config::load(Some(&path)).unwrap_err();
+            assert!(
+                format!("{:#}", e).contains("invalid length 0, expected at least one value"),
+                "{:#}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn given_options_win() {
+        let preset = Preset {
+            filter: Some("celt/".to_string()),
+            exclude: Some("tests/".to_string()),
+            y_coord: Some(vec![YCoord::Expressions]),
+            width: Some(1200),
+            ..Preset::default()
+        };
+        let mut filter = Some("src/".to_string());
+        let mut exclude = None;
+        let mut x_coord = XCoord::Date;
+        let mut y_coord = vec![YCoord::Functions];
+        let mut count_kinds = Vec::new();
+        let mut palette = Palette::Cud;
+        let (mut width, mut height) = (None, Some(300));
+        preset.apply(
+            PresetOptions {
+                filter: &mut filter,
+                exclude: &mut exclude,
+                x_coord: &mut x_coord,
+                y_coord: &mut y_coord,
+                count_kinds: &mut count_kinds,
+                palette: &mut palette,
+                width: &mut width,
+                height: &mut height,
+            },
+            |name| ["filter", "y_coord"].contains(&name),
+        );
+
+        // given, or not in the preset
+        assert_eq!(filter.as_deref(), Some("src/"));
+        assert_eq!(y_coord, [YCoord::Functions]);
+        assert!(matches!(x_coord, XCoord::Date));
+        assert!(count_kinds.is_empty());
+        assert_eq!(palette, Palette::Cud);
+        assert_eq!(height, Some(300));
+        // from the preset
+        assert_eq!(exclude.as_deref(), Some("tests/"));
+        assert_eq!(width, Some(1200));
+    }
+
+    #[test]
+    fn invalid_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "[presets.tiny]\nwidth = 10\n");
+        let e = Config::load(Some(&path)).unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("The size 10 of the preset `tiny`"),
+            "{}",
+            e
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Names of the repositories hosted on GitHub
+
+use std::fmt::Display;
+use thiserror::Error;
+
+/// What the URLs of the repositories start with
+pub const URL_PREFIX: &str = "https://github.com/";
+
+/// GitHub doesn't allow longer user and organization names
+const MAX_OWNER_LEN: usize = 39;
+/// GitHub doesn't allow longer repository names
+const MAX_NAME_LEN: usize = 100;
+
+/// A repository on GitHub, with the names checked against GitHub's naming rules
+///
+/// The names keep the spelling they were given in, GitHub matches them case-insensitively.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GithubRepo {
+    pub owner: String,
+    pub name: String,
+}
+
+/// A part of a [`GithubRepo`] GitHub wouldn't accept
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidGithubRepo {
+    #[error("Invalid GitHub owner name")]
+    Owner,
+    #[error("Invalid GitHub repository name")]
+    Name,
+}
+
+impl GithubRepo {
+    /// Checks the owner and the name of a repository, dropping the `.git` suffix of the name
+    ///
+    /// The owner can have ASCII letters, digits and dashes, but not start with a dash, and the name
+    /// can also have underscores and dots, but not be `.` or `..`.
+    ///
+    /// ```
+    /// use unsafe_track::github::{GithubRepo, InvalidGithubRepo};
+    ///
+    /// let repo = GithubRepo::parse("DCNick3", "unsafe-track.git").unwrap();
+    /// assert_eq!(repo.url(), "https://github.com/DCNick3/unsafe-track");
+    /// assert_eq!(repo.key(), "dcnick3/unsafe-track");
+    /// assert!(GithubRepo::parse("rust-lang", "rust_2024.x").is_ok());
+    ///
+    /// // a path can't be smuggled in
+    /// assert_eq!(GithubRepo::parse("../../evil", "x"), Err(InvalidGithubRepo::Owner));
+    /// assert_eq!(GithubRepo::parse("owner", ".."), Err(InvalidGithubRepo::Name));
+    /// assert_eq!(GithubRepo::parse("owner", "a/b"), Err(InvalidGithubRepo::Name));
+    /// assert_eq!(GithubRepo::parse("owner", ".git"), Err(InvalidGithubRepo::Name));
+    ///
+    /// // nor anything but ASCII
+    /// assert_eq!(GithubRepo::parse("ówner", "x"), Err(InvalidGithubRepo::Owner));
+    /// assert_eq!(GithubRepo::parse("owner", "rêpo"), Err(InvalidGithubRepo::Name));
+    /// assert_eq!(GithubRepo::parse("my owner", "x"), Err(InvalidGithubRepo::Owner));
+    ///
+    /// // or names longer than GitHub allows
+    /// assert!(GithubRepo::parse(&"a".repeat(39), &"b".repeat(100)).is_ok());
+    /// assert_eq!(GithubRepo::parse(&"a".repeat(40), "x"), Err(InvalidGithubRepo::Owner));
+    /// assert_eq!(GithubRepo::parse("owner", &"b".repeat(101)), Err(InvalidGithubRepo::Name));
+    /// assert_eq!(GithubRepo::parse("", "x"), Err(InvalidGithubRepo::Owner));
+    /// assert_eq!(GithubRepo::parse("-owner", "x"), Err(InvalidGithubRepo::Owner));
+    /// ```
+    pub fn parse(owner: &str, name: &str) -> Result<Self, InvalidGithubRepo> {
+        let owner_valid = (1..=MAX_OWNER_LEN).contains(&owner.len())
+            && !owner.starts_with('-')
+            && owner
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        if !owner_valid {
+            return Err(InvalidGithubRepo::Owner);
+        }
+
+        let name = name.strip_suffix(".git").unwrap_or(name);
+        let name_valid = (1..=MAX_NAME_LEN).contains(&name.len())
+            && name != "."
+            && name != ".."
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+        if !name_valid {
+            return Err(InvalidGithubRepo::Name);
+        }
+
+        Ok(Self {
+            owner: owner.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// The URL the repository is fetched from
+    pub fn url(&self) -> String {
+        format!("{}{}", URL_PREFIX, self)
+    }
+
+    /// `owner/name` in lowercase, the same for all the spellings GitHub accepts
+    pub fn key(&self) -> String {
+        self.to_string().to_lowercase()
+    }
+}
+
+impl Display for GithubRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
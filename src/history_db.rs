@@ -0,0 +1,541 @@
+//! A SQLite database accumulating the results of the runs, so the history can be plotted after
+//! the old commits are gone from the repository
+
+use cargo_geiger_serde::{Count, CounterBlock};
+use gix_hash::ObjectId;
+use regex::Regex;
+use rusqlite::{named_params, Connection, Row, TransactionBehavior};
+use std::path::Path;
+use std::time::Duration;
+use unsafe_track::analysis::{self, CommitResult, ExtraMetrics, PathFilter};
+
+/// How long a run waits for another one holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The schema changes, in order. The applied ones are recorded in the `migrations` table.
+const MIGRATIONS: &[&str] = &[
+    r"
+CREATE TABLE commits (
+    repository TEXT NOT NULL,
+    oid TEXT NOT NULL,
+    date INTEGER NOT NULL,
+    date_offset INTEGER NOT NULL,
+    matched_files INTEGER NOT NULL,
+    failed_files INTEGER NOT NULL,
+    functions_safe INTEGER NOT NULL,
+    functions_unsafe INTEGER NOT NULL,
+    exprs_safe INTEGER NOT NULL,
+    exprs_unsafe INTEGER NOT NULL,
+    item_impls_safe INTEGER NOT NULL,
+    item_impls_unsafe INTEGER NOT NULL,
+    item_traits_safe INTEGER NOT NULL,
+    item_traits_unsafe INTEGER NOT NULL,
+    methods_safe INTEGER NOT NULL,
+    methods_unsafe INTEGER NOT NULL,
+    total_loc INTEGER NOT NULL,
+    ffi_functions INTEGER NOT NULL,
+    unsafe_blocks INTEGER NOT NULL,
+    unsafe_functions INTEGER NOT NULL,
+    unsafe_impls INTEGER NOT NULL,
+    PRIMARY KEY (repository, oid)
+);
+",
+    r"
+-- unknown for the commits stored before, so not forbidding
+ALTER TABLE commits ADD COLUMN forbids_unsafe INTEGER NOT NULL DEFAULT 0;
+",
+    r"
+-- unknown for the commits stored before, none of their code counts as new
+ALTER TABLE commits ADD COLUMN introduced_functions_safe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_functions_unsafe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_exprs_safe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_exprs_unsafe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_item_impls_safe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_item_impls_unsafe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_item_traits_safe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_item_traits_unsafe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_methods_safe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE commits ADD COLUMN introduced_methods_unsafe INTEGER NOT NULL DEFAULT 0;
+",
+    r"
+-- the results of a commit depend on the filters, see `FilterColumns`. The ones of the commits
+-- stored before are NULL, as they weren't recorded.
+CREATE TABLE commits_by_filter (
+    repository TEXT NOT NULL,
+    include TEXT,
+    exclude TEXT,
+    use_ignore_file INTEGER,
+    skip_vendored INTEGER,
+    subdir TEXT,
+    expand_local_macros INTEGER,
+    oid TEXT NOT NULL,
+    date INTEGER NOT NULL,
+    date_offset INTEGER NOT NULL,
+    matched_files INTEGER NOT NULL,
+    failed_files INTEGER NOT NULL,
+    functions_safe INTEGER NOT NULL,
+    functions_unsafe INTEGER NOT NULL,
+    exprs_safe INTEGER NOT NULL,
+    exprs_unsafe INTEGER NOT NULL,
+    item_impls_safe INTEGER NOT NULL,
+    item_impls_unsafe INTEGER NOT NULL,
+    item_traits_safe INTEGER NOT NULL,
+    item_traits_unsafe INTEGER NOT NULL,
+    methods_safe INTEGER NOT NULL,
+    methods_unsafe INTEGER NOT NULL,
+    total_loc INTEGER NOT NULL,
+    ffi_functions INTEGER NOT NULL,
+    unsafe_blocks INTEGER NOT NULL,
+    unsafe_functions INTEGER NOT NULL,
+    unsafe_impls INTEGER NOT NULL,
+    forbids_unsafe INTEGER NOT NULL,
+    introduced_functions_safe INTEGER NOT NULL,
+    introduced_functions_unsafe INTEGER NOT NULL,
+    introduced_exprs_safe INTEGER NOT NULL,
+    introduced_exprs_unsafe INTEGER NOT NULL,
+    introduced_item_impls_safe INTEGER NOT NULL,
+    introduced_item_impls_unsafe INTEGER NOT NULL,
+    introduced_item_traits_safe INTEGER NOT NULL,
+    introduced_item_traits_unsafe INTEGER NOT NULL,
+    introduced_methods_safe INTEGER NOT NULL,
+    introduced_methods_unsafe INTEGER NOT NULL,
+    PRIMARY KEY (
+        repository, include, exclude, use_ignore_file, skip_vendored, subdir, expand_local_macros,
+        oid
+    )
+);
+INSERT INTO commits_by_filter (
+    repository, oid, date, date_offset, matched_files, failed_files, functions_safe,
+    functions_unsafe, exprs_safe, exprs_unsafe, item_impls_safe, item_impls_unsafe,
+    item_traits_safe, item_traits_unsafe, methods_safe, methods_unsafe, total_loc, ffi_functions,
+    unsafe_blocks, unsafe_functions, unsafe_impls, forbids_unsafe, introduced_functions_safe,
+    introduced_functions_unsafe, introduced_exprs_safe, introduced_exprs_unsafe,
+    introduced_item_impls_safe, introduced_item_impls_unsafe, introduced_item_traits_safe,
+    introduced_item_traits_unsafe, introduced_methods_safe, introduced_methods_unsafe
+)
+SELECT
+    repository, oid, date, date_offset, matched_files, failed_files, functions_safe,
+    functions_unsafe, exprs_safe, exprs_unsafe, item_impls_safe, item_impls_unsafe,
+    item_traits_safe, item_traits_unsafe, methods_safe, methods_unsafe, total_loc, ffi_functions,
+    unsafe_blocks, unsafe_functions, unsafe_impls, forbids_unsafe, introduced_functions_safe,
+    introduced_functions_unsafe, introduced_exprs_safe, introduced_exprs_unsafe,
+    introduced_item_impls_safe, introduced_item_impls_unsafe, introduced_item_traits_safe,
+    introduced_item_traits_unsafe, introduced_methods_safe, introduced_methods_unsafe
+FROM commits;
+DROP TABLE commits;
+ALTER TABLE commits_by_filter RENAME TO commits;
+",
+];
+
+/// The [`PathFilter`] the results of a commit were analysed with, as stored in the columns of the
+/// same names. `None` stands for the commits stored before the filters were recorded.
+///
+/// The missing `exclude` and `subdir` are stored empty. An empty `exclude` would leave out all the
+/// files, so no results are stored with it.
+struct FilterColumns<'a> {
+    include: Option<&'a str>,
+    exclude: Option<&'a str>,
+    use_ignore_file: Option<bool>,
+    skip_vendored: Option<bool>,
+    subdir: Option<String>,
+    expand_local_macros: Option<bool>,
+}
+
+impl<'a> FilterColumns<'a> {
+    fn new(path_filter: Option<&'a PathFilter>) -> Self {
+        Self {
+            include: path_filter.map(|f| f.include.as_str()),
+            exclude: path_filter.map(|f| f.exclude.as_ref().map_or("", Regex::as_str)),
+            use_ignore_file: path_filter.map(|f| f.use_ignore_file),
+            skip_vendored: path_filter.map(|f| f.skip_vendored),
+            // `rust/` and `rust` are the same directory
+            subdir: path_filter.map(|f| {
+                let subdir = f.subdir.as_deref().unwrap_or_default().split('/');
+                subdir
+                    .filter(|c| !c.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            }),
+            expand_local_macros: path_filter.map(|f| f.expand_local_macros),
+        }
+    }
+}
+
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Opens the database, creating it or bringing its schema up to date if needed
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // lets the readers go on while a run is writing
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        // taking the write lock upfront, so two runs don't apply the same migration
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS migrations (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL)",
+            [],
+        )?;
+        let applied: usize =
+            tx.query_row("SELECT COUNT(*) FROM migrations", [], |row| row.get(0))?;
+        for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            tx.execute_batch(migration)?;
+            tx.execute(
+                "INSERT INTO migrations (version, applied_at) VALUES (?1, unixepoch())",
+                [version + 1],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(Self { conn })
+    }
+
+    /// Stores the results of the commits of the repository analysed with `path_filter`, keeping the
+    /// ones already stored. Returns how many were added.
+    pub fn insert(
+        &mut self,
+        repository: &str,
+        path_filter: &PathFilter,
+        results: &[CommitResult],
+    ) -> rusqlite::Result<usize> {
+        let filter = FilterColumns::new(Some(path_filter));
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut inserted = 0;
+        {
+            let mut statement = tx.prepare(
+                "INSERT INTO commits (
+                    repository, include, exclude, use_ignore_file, skip_vendored, subdir,
+                    expand_local_macros, oid, date, date_offset, matched_files, failed_files,
+                    functions_safe, functions_unsafe, exprs_safe, exprs_unsafe, item_impls_safe,
+                    item_impls_unsafe, item_traits_safe, item_traits_unsafe, methods_safe,
+                    methods_unsafe, total_loc, ffi_functions, unsafe_blocks, unsafe_functions,
+                    unsafe_impls, forbids_unsafe, introduced_functions_safe,
+                    introduced_functions_unsafe, introduced_exprs_safe, introduced_exprs_unsafe,
+                    introduced_item_impls_safe, introduced_item_impls_unsafe,
+                    introduced_item_traits_safe, introduced_item_traits_unsafe,
+                    introduced_methods_safe, introduced_methods_unsafe
+                ) VALUES (
+                    :repository, :include, :exclude, :use_ignore_file, :skip_vendored, :subdir,
+                    :expand_local_macros, :oid, :date, :date_offset, :matched_files,
+                    :failed_files, :functions_safe, :functions_unsafe, :exprs_safe, :exprs_unsafe,
+                    :item_impls_safe, :item_impls_unsafe, :item_traits_safe, :item_traits_unsafe,
+                    :methods_safe, :methods_unsafe, :total_loc, :ffi_functions, :unsafe_blocks,
+                    :unsafe_functions, :unsafe_impls, :forbids_unsafe, :introduced_functions_safe,
+                    :introduced_functions_unsafe, :introduced_exprs_safe, :introduced_exprs_unsafe,
+                    :introduced_item_impls_safe, :introduced_item_impls_unsafe,
+                    :introduced_item_traits_safe, :introduced_item_traits_unsafe,
+                    :introduced_methods_safe, :introduced_methods_unsafe
+                )
+                ON CONFLICT DO NOTHING",
+            )?;
+            for r in results {
+                let c = &r.counters;
+                let i = &r.introduced_counters;
+                inserted += statement.execute(named_params! {
+                    ":repository": repository,
+                    ":include": filter.include,
+                    ":exclude": filter.exclude,
+                    ":use_ignore_file": filter.use_ignore_file,
+                    ":skip_vendored": filter.skip_vendored,
+                    ":subdir": filter.subdir,
+                    ":expand_local_macros": filter.expand_local_macros,
+                    ":oid": r.oid.to_string(),
+                    ":date": r.date.seconds,
+                    ":date_offset": r.date.offset,
+                    ":matched_files": r.matched_files_count,
+                    ":failed_files": r.failed_files_count,
+                    ":functions_safe": c.functions.safe,
+                    ":functions_unsafe": c.functions.unsafe_,
+                    ":exprs_safe": c.exprs.safe,
+                    ":exprs_unsafe": c.exprs.unsafe_,
+                    ":item_impls_safe": c.item_impls.safe,
+                    ":item_impls_unsafe": c.item_impls.unsafe_,
+                    ":item_traits_safe": c.item_traits.safe,
+                    ":item_traits_unsafe": c.item_traits.unsafe_,
+                    ":methods_safe": c.methods.safe,
+                    ":methods_unsafe": c.methods.unsafe_,
+                    ":total_loc": r.total_loc,
+                    ":ffi_functions": r.extra.ffi_functions,
+                    ":unsafe_blocks": r.extra.unsafe_blocks,
+                    ":unsafe_functions": r.extra.unsafe_functions,
+                    ":unsafe_impls": r.extra.unsafe_impls,
+                    ":forbids_unsafe": r.forbids_unsafe,
+                    ":introduced_functions_safe": i.functions.safe,
+                    ":introduced_functions_unsafe": i.functions.unsafe_,
+                    ":introduced_exprs_safe": i.exprs.safe,
+                    ":introduced_exprs_unsafe": i.exprs.unsafe_,
+                    ":introduced_item_impls_safe": i.item_impls.safe,
+                    ":introduced_item_impls_unsafe": i.item_impls.unsafe_,
+                    ":introduced_item_traits_safe": i.item_traits.safe,
+                    ":introduced_item_traits_unsafe": i.item_traits.unsafe_,
+                    ":introduced_methods_safe": i.methods.safe,
+                    ":introduced_methods_unsafe": i.methods.unsafe_,
+                })?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// All the stored commits of the repository analysed with `path_filter`, sorted by date. The
+    /// ones stored before the filters were recorded without it.
+    pub fn history(
+        &self,
+        repository: &str,
+        path_filter: Option<&PathFilter>,
+    ) -> rusqlite::Result<Vec<CommitResult>> {
+        let filter = FilterColumns::new(path_filter);
+        // `IS` for the NULL filters to match
+        let mut statement = self.conn.prepare(
+            "SELECT * FROM commits
+            WHERE repository = :repository AND include IS :include AND exclude IS :exclude
+                AND use_ignore_file IS :use_ignore_file AND skip_vendored IS :skip_vendored
+                AND subdir IS :subdir AND expand_local_macros IS :expand_local_macros
+            ORDER BY date, oid",
+        )?;
+        let rows = statement.query_map(
+            named_params! {
+                ":repository": repository,
+                ":include": filter.include,
+                ":exclude": filter.exclude,
+                ":use_ignore_file": filter.use_ignore_file,
+                ":skip_vendored": filter.skip_vendored,
+                ":subdir": filter.subdir,
+                ":expand_local_macros": filter.expand_local_macros,
+            },
+            commit_from_row,
+        )?;
+        let mut commits = rows
+            .enumerate()
+            .map(|(index, row)| {
+                row.map(|mut commit| {
+                    commit.index = index as u32;
+                    commit
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        analysis::abbreviate(&mut commits);
+        Ok(commits)
+    }
+}
+
+fn commit_from_row(row: &Row) -> rusqlite::Result<CommitResult> {
+    let count = |safe, unsafe_| -> rusqlite::Result<Count> {
+        Ok(Count {
+            safe: row.get(safe)?,
+            unsafe_: row.get(unsafe_)?,
+        })
+    };
+    let oid: String = row.get("oid")?;
+    Ok(CommitResult {
+        oid: ObjectId::from_hex(oid.as_bytes()).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, e.into())
+        })?,
+        // assigned once all the rows are read, like the abbreviation
+        index: 0,
+        date: gix_date::Time::new(row.get("date")?, row.get("date_offset")?),
+        matched_files_count: row.get("matched_files")?,
+        failed_files_count: row.get("failed_files")?,
+        counters: CounterBlock {
+            functions: count("functions_safe", "functions_unsafe")?,
+            exprs: count("exprs_safe", "exprs_unsafe")?,
+            item_impls: count("item_impls_safe", "item_impls_unsafe")?,
+            item_traits: count("item_traits_safe", "item_traits_unsafe")?,
+            methods: count("methods_safe", "methods_unsafe")?,
+        },
+        introduced_counters: CounterBlock {
+            functions: count("introduced_functions_safe", "introduced_functions_unsafe")?,
+            exprs: count("introduced_exprs_safe", "introduced_exprs_unsafe")?,
+            item_impls: count("introduced_item_impls_safe", "introduced_item_impls_unsafe")?,
+            item_traits: count(
+                "introduced_item_traits_safe",
+                "introduced_item_traits_unsafe",
+            )?,
+            methods: count("introduced_methods_safe", "introduced_methods_unsafe")?,
+        },
+        total_loc: row.get("total_loc")?,
+        extra: ExtraMetrics {
+            ffi_functions: row.get("ffi_functions")?,
+            unsafe_blocks: row.get("unsafe_blocks")?,
+            unsafe_functions: row.get("unsafe_functions")?,
+            unsafe_impls: row.get("unsafe_impls")?,
+        },
+        forbids_unsafe: row.get("forbids_unsafe")?,
+        abbrev_len: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPOSITORY: &str = "https://github.com/owner/repo";
+
+    fn filter(subdir: Option<&str>) -> PathFilter {
+        PathFilter {
+            include: Regex::new(r"\.rs$").unwrap(),
+            exclude: None,
+            use_ignore_file: true,
+            skip_vendored: false,
+            subdir: subdir.map(String::from),
+            expand_local_macros: false,
+        }
+    }
+
+    fn commit(hex_digit: char, date: i64, unsafe_functions: u64) -> CommitResult {
+        CommitResult {
+            oid: ObjectId::from_hex(hex_digit.to_string().repeat(40).as_bytes()).unwrap(),
+            index: 0,
+            date: gix_date::Time::new(date, 3600),
+            matched_files_count: 2,
+            failed_files_count: 0,
+            counters: CounterBlock {
+                functions: Count {
+                    safe: 10,
+                    unsafe_: unsafe_functions,
+                },
+                ..Default::default()
+            },
+            introduced_counters: CounterBlock::default(),
+            total_loc: 100,
+            extra: ExtraMetrics::default(),
+            forbids_unsafe: false,
+            abbrev_len: 0,
+        }
+    }
+
+    fn open_temp() -> (tempfile::TempDir, HistoryDb) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = HistoryDb::open(&dir.path().join("history.sqlite")).unwrap();
+        (dir, db)
+    }
+
+    #[test]
+    fn duplicate_inserts() {
+        let (_dir, mut db) = open_temp();
+        let results = [commit('a', 1000, 1), commit('b', 2000, 2)];
+        assert_eq!(db.insert(REPOSITORY, &filter(None), &results).unwrap(), 2);
+        // the results stored first are kept
+        let again = [commit('a', 1000, 5), commit('c', 3000, 3)];
+        assert_eq!(db.insert(REPOSITORY, &filter(None), &again).unwrap(), 1);
+        // another filter or repository has results of its own
+        let other = PathFilter {
+            exclude: Some(Regex::new("tests/").unwrap()),
+            ..filter(None)
+        };
+        assert_eq!(db.insert(REPOSITORY, &other, &again).unwrap(), 2);
+        assert_eq!(db.insert("other", &filter(None), &again).unwrap(), 2);
+
+        let history = db.history(REPOSITORY, Some(&filter(None))).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].counters.functions.unsafe_, 1);
+        assert_eq!(
+            db.history(REPOSITORY, Some(&other)).unwrap()[0]
+                .counters
+                .functions
+                .unsafe_,
+            5
+        );
+    }
+
+    #[test]
+    fn history_order() {
+        let (_dir, mut db) = open_temp();
+        // the commits of the same second are ordered by their ids
+        let results = [
+            commit('c', 3000, 3),
+            commit('b', 1000, 2),
+            commit('a', 2000, 1),
+            commit('d', 1000, 4),
+        ];
+        db.insert(REPOSITORY, &filter(None), &results).unwrap();
+
+        let history = db.history(REPOSITORY, Some(&filter(None))).unwrap();
+        let order = history
+            .iter()
+            .map(|r| (r.index, r.counters.functions.unsafe_))
+            .collect::<Vec<_>>();
+        assert_eq!(order, [(0, 2), (1, 4), (2, 1), (3, 3)]);
+        assert_eq!(history[0].date, gix_date::Time::new(1000, 3600));
+        assert!(history
+            .iter()
+            .all(|r| r.abbrev_len == analysis::MIN_ABBREV_LEN));
+        assert_eq!(history[0].short_oid(), "bbbbbbb");
+        assert!(db.history(REPOSITORY, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrate_old_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sqlite");
+        // as left by a version with the first three migrations
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE migrations (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL);
+            INSERT INTO migrations VALUES (1, 0), (2, 0), (3, 0);",
+        )
+        .unwrap();
+        for migration in &MIGRATIONS[..3] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO commits (
+                repository, oid, date, date_offset, matched_files, failed_files, functions_safe,
+                functions_unsafe, exprs_safe, exprs_unsafe, item_impls_safe, item_impls_unsafe,
+                item_traits_safe, item_traits_unsafe, methods_safe, methods_unsafe, total_loc,
+                ffi_functions, unsafe_blocks, unsafe_functions, unsafe_impls
+            ) VALUES (?1, ?2, 1000, 3600, 2, 0, 10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 0)",
+            [REPOSITORY, &"a".repeat(40)],
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut db = HistoryDb::open(&path).unwrap();
+        let versions: usize = db
+            .conn
+            .query_row("SELECT MAX(version) FROM migrations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(versions, MIGRATIONS.len());
+        // kept without a filter
+        let history = db.history(REPOSITORY, None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].oid, commit('a', 1000, 1).oid);
+        assert_eq!(history[0].counters.functions.unsafe_, 1);
+        assert!(db
+            .history(REPOSITORY, Some(&filter(None)))
+            .unwrap()
+            .is_empty());
+        // the same commit can now be stored with its filter
+        let results = [commit('a', 1000, 1)];
+        assert_eq!(db.insert(REPOSITORY, &filter(None), &results).unwrap(), 1);
+    }
+
+    #[test]
+    fn normalized_subdir() {
+        let (_dir, mut db) = open_temp();
+        let results = [commit('a', 1000, 1)];
+        assert_eq!(
+            db.insert(REPOSITORY, &filter(Some("rust/")), &results)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.insert(REPOSITORY, &filter(Some("/rust")), &results)
+                .unwrap(),
+            0
+        );
+        let history = db.history(REPOSITORY, Some(&filter(Some("rust")))).unwrap();
+        assert_eq!(history.len(), 1);
+        // `/` is the root, like no subdirectory
+        assert_eq!(
+            db.insert(REPOSITORY, &filter(Some("/")), &results).unwrap(),
+            1
+        );
+        assert_eq!(db.insert(REPOSITORY, &filter(None), &results).unwrap(), 0);
+    }
+}
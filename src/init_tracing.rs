@@ -2,7 +2,7 @@
 // 2. add `init_tracing::init_tracing().whatever_context("Setting up the opentelemetry exporter")?;` to main.rs
 
 use anyhow::Context as _;
-use std::{panic::PanicInfo, time::Duration};
+use std::{panic::PanicHookInfo, time::Duration};
 
 use opentelemetry::{
     propagation::{TextMapCompositePropagator, TextMapPropagator},
@@ -18,7 +18,17 @@ use tracing_subscriber::{
     fmt::format::FmtSpan, layer::SubscriberExt, registry::Registry, util::SubscriberInitExt,
 };
 
-fn panic_hook(panic_info: &PanicInfo) {
+/// How the events are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per event
+    #[default]
+    Compact,
+    /// One JSON object per line, for the log aggregation systems
+    Json,
+}
+
+fn panic_hook(panic_info: &PanicHookInfo) {
     let backtrace = std::backtrace::Backtrace::force_capture();
 
     let payload = panic_info.payload();
@@ -100,7 +110,7 @@ fn propagator_from_string(
 fn init_tracer() -> Result<sdktrace::Tracer, anyhow::Error> {
     let mut exporter = opentelemetry_otlp::new_exporter().tonic();
 
-    println!(
+    eprintln!(
         "Using opentelemetry endpoint {}",
         exporter.export_config().endpoint
     );
@@ -116,7 +126,7 @@ fn init_tracer() -> Result<sdktrace::Tracer, anyhow::Error> {
         ],
     );
 
-    println!("Using opentelemetry resources {:?}", resource);
+    eprintln!("Using opentelemetry resources {:?}", resource);
 
     opentelemetry_otlp::new_pipeline()
         .tracing()
@@ -126,16 +136,36 @@ fn init_tracer() -> Result<sdktrace::Tracer, anyhow::Error> {
         .context("Setting up the opentelemetry tracer")
 }
 
-pub fn init_tracing() -> Result<(), anyhow::Error> {
+/// Whether an OTLP collector is configured, the exporter would only retry against the default
+/// endpoint otherwise
+fn otlp_endpoint_configured() -> bool {
+    [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+}
+
+/// Logs only the warnings and the errors of the crate when `quiet`, unless `RUST_LOG` says otherwise
+pub fn init_tracing(quiet: bool, format: LogFormat) -> Result<(), anyhow::Error> {
     std::panic::set_hook(Box::new(|panic_info| {
         panic_hook(panic_info);
     }));
 
-    let tracer = init_tracer().context("Setting up the opentelemetry exporter")?;
+    let tracer = if otlp_endpoint_configured() {
+        Some(init_tracer().context("Setting up the opentelemetry exporter")?)
+    } else {
+        None
+    };
 
-    let default = concat!(env!("CARGO_CRATE_NAME"), "=trace")
-        .parse()
-        .expect("hard-coded default directive should be valid");
+    let default = if quiet {
+        concat!(env!("CARGO_CRATE_NAME"), "=warn")
+    } else {
+        concat!(env!("CARGO_CRATE_NAME"), "=trace")
+    }
+    .parse()
+    .expect("hard-coded default directive should be valid");
 
     Registry::default()
         .with(
@@ -144,12 +174,20 @@ pub fn init_tracing() -> Result<(), anyhow::Error> {
                 .from_env_lossy()
                 .add_directive("otel::tracing=trace".parse().unwrap()),
         )
-        .with(
+        .with((format == LogFormat::Compact).then(|| {
+            // stdout is left to the output of `analyse`
             tracing_subscriber::fmt::Layer::new()
+                .with_writer(std::io::stderr)
                 .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-                .event_format(tracing_subscriber::fmt::format::Format::default().compact()),
-        )
-        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .event_format(tracing_subscriber::fmt::format::Format::default().compact())
+        }))
+        .with((format == LogFormat::Json).then(|| {
+            tracing_subscriber::fmt::Layer::new()
+                .json()
+                .with_writer(std::io::stderr)
+                .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
+        }))
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
         .init();
 
     init_propagator().context("Setting up the opentelemetry propagator")?;
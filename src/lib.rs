@@ -0,0 +1,68 @@
+//! Tracks the usage of `unsafe` across the history of a Rust repository.
+//!
+//! The whole history is fetched in a single pack and every commit is analysed with
+//! [geiger](https://docs.rs/geiger). The results can then be rendered as a chart.
+//!
+//! The commonly used items are re-exported here, the [`analysis`] and [`plot`] modules have the
+//! rest. Failures are reported as [`AnalysisError`] and [`PlotError`], which can be matched on.
+//!
+//! ```no_run
+//! use regex::Regex;
+//! use std::sync::atomic::AtomicBool;
+//! use unsafe_track::{
+//!     analyse_repo, plot_results_svg, AnalysisCache, AnalysisError, AnalysisLimits, PathFilter,
+//!     PlotOptions, XCoord, YCoord,
+//! };
+//!
+//! // about 16 MiB worth of file results
+//! let cache = AnalysisCache::new(16 << 20);
+//! let analysis = analyse_repo(
+//!     &cache,
+//!     "/path/to/fixture/repo",
+//!     "HEAD",
+//!     PathFilter {
+//!         include: Regex::new(r"\.rs$").unwrap(),
+//!         exclude: None,
+//!         use_ignore_file: true,
+//!         skip_vendored: false,
+//!         subdir: None,
+//!         expand_local_macros: false,
+//!     },
+//!     &AnalysisLimits::default(),
+//!     &AtomicBool::new(false),
+//! );
+//! let analysis = match analysis {
+//!     Ok(analysis) => analysis,
+//!     Err(AnalysisError::RefNotFound(name)) => panic!("no ref {}", name),
+//!     Err(e) => return Err(e.into()),
+//! };
+//!
+//! for commit in &analysis.commits {
+//!     let counts = YCoord::Expressions.get_counts(commit);
+//!     println!("{}: {} unsafe expressions", commit.oid, counts.unsafe_);
+//! }
+//!
+//! unsafe_track::plot::register_font(None);
+//! let mut options = PlotOptions::default();
+//! options.x_coord = XCoord::Date;
+//! options.y_coord = YCoord::Expressions;
+//! let svg = plot_results_svg(&analysis.commits, None, &options)?;
+//! # drop(svg);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod analysis;
+#[cfg(test)]
+#[path = "../benches/fixture/mod.rs"]
+mod fixture;
+pub mod github;
+pub mod plot;
+
+pub use analysis::{
+    analyse_repo, analyse_repo_refs, AnalysisCache, AnalysisError, AnalysisLimits, CommitResult,
+    PathFilter, RepoAnalysis,
+};
+pub use plot::{
+    plot_comparison, plot_comparison_svg, plot_results, plot_results_into, plot_results_svg,
+    PlotError, PlotOptions, XCoord, YCoord,
+};
@@ -0,0 +1,483 @@
+//! Renders the analysed histories in the format picked with `--format`
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use unsafe_track::analysis::{AnalysisPlan, CommitResult};
+use unsafe_track::plot;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One row per commit with the counts of the first `--y-coord`, aligned on a terminal and
+    /// tab-separated otherwise
+    #[default]
+    Table,
+    /// The chart
+    Svg,
+    /// All the counters of every commit
+    Json,
+    /// One row per commit with the counts of the first `--y-coord`
+    Csv,
+    /// The unsafe share of each kind of item in the head commit, as a text histogram
+    Breakdown,
+}
+
+/// A column of the `table` format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    /// The shortest prefix of the commit id telling it apart from the others
+    Oid,
+    /// The committer date
+    Date,
+    /// Position of the commit in the date-sorted history
+    Index,
+    /// Number of matching files that could not be analysed
+    Failed,
+    /// The safe count of the first `--y-coord`, or its whole count if it isn't split by safety
+    Safe,
+    /// The unsafe count of the first `--y-coord`, empty if it isn't split by safety
+    Unsafe,
+    /// The unsafe share of the first `--y-coord`, empty if it isn't split by safety
+    Ratio,
+    /// Lines of the matching files that are neither blank nor comment-only
+    Loc,
+}
+
+/// What the rows of the `table` format are sorted by
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Date,
+    /// The unsafe count of the first `--y-coord`, or its whole count if it isn't split by safety
+    Unsafe,
+    Ratio,
+}
+
+/// How the `table` format lays the commits out
+#[derive(Args, Debug)]
+pub struct TableArgs {
+    /// Columns of the `table` format, comma-separated. `oid,date,failed,unsafe,safe` by default,
+    /// without `unsafe` if the first `--y-coord` isn't split by safety.
+    #[clap(long, value_enum, value_delimiter = ',')]
+    columns: Vec<Column>,
+    /// Sort the rows of the `table` format, which are in the order of the history otherwise. The
+    /// histories of `--compare-ref` are sorted separately.
+    #[clap(long, value_enum)]
+    sort: Option<SortKey>,
+    /// Reverse the order of the rows of the `table` format
+    #[clap(long)]
+    reverse: bool,
+}
+
+/// What the table is laid out with, the other formats don't need it
+pub struct TableLayout<'a> {
+    table: &'a TableArgs,
+    /// Whether to pad the columns for reading, instead of separating them with tabs for `cut` and
+    /// `awk`
+    aligned: bool,
+}
+
+/// What the chart is drawn with, the other formats don't need it
+pub struct ChartOptions {
+    pub options: plot::PlotOptions,
+    pub metadata: Option<plot::ChartMetadata>,
+}
+
+#[derive(Serialize)]
+struct JsonHistory<'a> {
+    #[serde(rename = "ref")]
+    ref_name: &'a str,
+    commits: Vec<JsonCommit<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonCommit<'a> {
+    #[serde(flatten)]
+    commit: &'a CommitResult,
+    short_oid: String,
+}
+
+/// Writes the histories to `out`, or to stdout without it
+pub fn write(
+    format: OutputFormat,
+    out: Option<&Path>,
+    histories: &[(&str, &[CommitResult])],
+    y_coord: plot::YCoord,
+    table: &TableArgs,
+    chart: &ChartOptions,
+) -> anyhow::Result<()> {
+    let layout = TableLayout {
+        table,
+        aligned: out.is_none() && std::io::stdout().is_terminal(),
+    };
+    let rendered = render(format, histories, y_coord, &layout, chart)?;
+    write_out(out, &rendered)
+}
+
+/// Writes the plan of `--plan-only` as JSON with `--format json`, as text otherwise
+pub fn write_plan(
+    format: OutputFormat,
+    out: Option<&Path>,
+    plan: &AnalysisPlan,
+) -> anyhow::Result<()> {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    if format == OutputFormat::Json {
+        buf = serde_json::to_string_pretty(plan)?;
+        buf.push('\n');
+    } else {
+        writeln!(buf, "Commits: {}", plan.commit_count)?;
+        writeln!(
+            buf,
+            "Matching files: {} ({} bytes), {} of them cached",
+            plan.blob_count, plan.blob_bytes, plan.cached_blobs
+        )?;
+        if plan.skipped_commits > 0 {
+            writeln!(buf, "Skipped commits: {}", plan.skipped_commits)?;
+        }
+        if !plan.largest_files.is_empty() {
+            writeln!(buf, "Largest files:")?;
+            for file in &plan.largest_files {
+                writeln!(buf, "{:>12} {}", file.bytes, file.path)?;
+            }
+        }
+    }
+    write_out(out, &buf)
+}
+
+fn write_out(out: Option<&Path>, rendered: &str) -> anyhow::Result<()> {
+    match out {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => std::io::stdout().lock().write_all(rendered.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn render(
+    format: OutputFormat,
+    histories: &[(&str, &[CommitResult])],
+    y_coord: plot::YCoord,
+    layout: &TableLayout,
+    chart: &ChartOptions,
+) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    match format {
+        OutputFormat::Table => buf = table(histories, y_coord, layout.table, layout.aligned),
+        OutputFormat::Svg => {
+            let metadata = chart.metadata.as_ref();
+            buf = match histories {
+                [(_, results)] => plot::plot_results_svg(results, metadata, &chart.options),
+                histories => plot::plot_comparison_svg(histories, metadata, &chart.options),
+            }?;
+        }
+        OutputFormat::Json => {
+            let histories = histories
+                .iter()
+                .map(|&(ref_name, commits)| JsonHistory {
+                    ref_name,
+                    commits: commits
+                        .iter()
+                        .map(|commit| JsonCommit {
+                            commit,
+                            short_oid: commit.short_oid(),
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            buf = serde_json::to_string_pretty(&histories)?;
+            buf.push('\n');
+        }
+        OutputFormat::Csv => {
+            buf.push_str(CSV_HEADER);
+            for &(name, results) in histories {
+                for r in results {
+                    buf.push_str(&csv_row(name, r, y_coord));
+                }
+            }
+        }
+        OutputFormat::Breakdown => {
+            for &(name, results) in histories {
+                if histories.len() > 1 {
+                    writeln!(buf, "{}:", name)?;
+                }
+                match results.last() {
+                    Some(head) => buf.push_str(&breakdown(head)),
+                    None => writeln!(buf, "No commits")?,
+                }
+            }
+        }
+    }
+    Ok(buf)
+}
+
+/// The commits as a table with a header row, with a `ref` column first when there are several
+/// histories
+fn table(
+    histories: &[(&str, &[CommitResult])],
+    y_coord: plot::YCoord,
+    args: &TableArgs,
+    aligned: bool,
+) -> String {
+    let columns = if !args.columns.is_empty() {
+        args.columns.clone()
+    } else if y_coord.is_split() {
+        vec![
+            Column::Oid,
+            Column::Date,
+            Column::Failed,
+            Column::Unsafe,
+            Column::Safe,
+        ]
+    } else {
+        vec![Column::Oid, Column::Date, Column::Failed, Column::Safe]
+    };
+    let with_ref = histories.len() > 1;
+
+    let mut header = Vec::new();
+    if with_ref {
+        header.push("ref");
+    }
+    header.extend(columns.iter().map(|column| match column {
+        Column::Oid => "oid",
+        Column::Date => "date",
+        Column::Index => "index",
+        Column::Failed => "failed",
+        Column::Safe if !y_coord.is_split() => "count",
+        Column::Safe => "safe",
+        Column::Unsafe => "unsafe",
+        Column::Ratio => "ratio",
+        Column::Loc => "loc",
+    }));
+    let mut rows = vec![header.into_iter().map(String::from).collect::<Vec<_>>()];
+
+    for &(name, results) in histories {
+        let mut results = results.iter().collect::<Vec<_>>();
+        if let Some(key) = args.sort {
+            // stable, the commits sorting the same stay in the order of the history
+            results.sort_by(|a, b| sort_key_cmp(key, y_coord, a, b));
+        }
+        if args.reverse {
+            results.reverse();
+        }
+
+        for r in results {
+            let counts = y_coord.get_counts(r);
+            let mut row = Vec::new();
+            if with_ref {
+                row.push(name.to_string());
+            }
+            row.extend(columns.iter().map(|column| match column {
+                Column::Oid => r.short_oid(),
+                Column::Date => r.date.format(gix_date::time::format::SHORT),
+                Column::Index => r.index.to_string(),
+                Column::Failed => r.failed_files_count.to_string(),
+                Column::Safe => counts.safe.to_string(),
+                Column::Unsafe if y_coord.is_split() => counts.unsafe_.to_string(),
+                Column::Ratio if y_coord.is_split() => {
+                    format!("{:.1}%", unsafe_share(y_coord, r) * 100.0)
+                }
+                Column::Unsafe | Column::Ratio => String::new(),
+                Column::Loc => r.total_loc.to_string(),
+            }));
+            rows.push(row);
+        }
+    }
+
+    // the ids, the dates and the ref names read best on the left, the numbers on the right
+    format_rows(rows, aligned, |i| {
+        i < with_ref as usize
+            || matches!(
+                columns.get(i - with_ref as usize),
+                Some(Column::Oid | Column::Date)
+            )
+    })
+}
+
+/// The final counts of every repository of `analyse --url-file`, with the ones that failed left
+/// empty. Aligned for reading when `aligned`, tab-separated otherwise.
+pub fn batch_summary(
+    repos: &[(&str, Option<&CommitResult>)],
+    y_coord: plot::YCoord,
+    aligned: bool,
+) -> String {
+    let split = y_coord.is_split();
+    let mut header = vec!["repository", "oid"];
+    if split {
+        header.extend(["unsafe", "safe", "ratio"]);
+    } else {
+        header.push("count");
+    }
+    let mut rows = vec![header.into_iter().map(String::from).collect::<Vec<_>>()];
+
+    for &(name, head) in repos {
+        let mut row = vec![name.to_string()];
+        match head {
+            Some(head) => {
+                let counts = y_coord.get_counts(head);
+                row.push(head.short_oid());
+                if split {
+                    row.push(counts.unsafe_.to_string());
+                }
+                row.push(counts.safe.to_string());
+                if split {
+                    row.push(format!("{:.1}%", unsafe_share(y_coord, head) * 100.0));
+                }
+            }
+            None => {
+                row.push("failed".to_string());
+                row.resize(rows[0].len(), String::new());
+            }
+        }
+        rows.push(row);
+    }
+    format_rows(rows, aligned, |i| i < 2)
+}
+
+/// Pads the columns for reading when `aligned`, the `text` ones on the left and the others on the
+/// right. Separates them with tabs for `cut` and `awk` otherwise.
+fn format_rows(rows: Vec<Vec<String>>, aligned: bool, text: impl Fn(usize) -> bool) -> String {
+    let mut buf = String::new();
+    if !aligned {
+        for row in rows {
+            buf.push_str(&row.join("\t"));
+            buf.push('\n');
+        }
+        return buf;
+    }
+
+    let widths = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for row in rows {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            if text(i) {
+                line.push_str(&format!("{:<width$}", cell, width = width));
+            } else {
+                line.push_str(&format!("{:>width$}", cell, width = width));
+            }
+        }
+        buf.push_str(line.trim_end());
+        buf.push('\n');
+    }
+    buf
+}
+
+/// Unsafe share of the counts of `y_coord`, 0 without any
+fn unsafe_share(y_coord: plot::YCoord, r: &CommitResult) -> f64 {
+    let counts = y_coord.get_counts(r);
+    match counts.unsafe_ + counts.safe {
+        0 => 0.0,
+        total => counts.unsafe_ as f64 / total as f64,
+    }
+}
+
+fn sort_key_cmp(
+    key: SortKey,
+    y_coord: plot::YCoord,
+    a: &CommitResult,
+    b: &CommitResult,
+) -> Ordering {
+    match key {
+        SortKey::Date => a.date.seconds.cmp(&b.date.seconds),
+        SortKey::Unsafe if y_coord.is_split() => y_coord
+            .get_counts(a)
+            .unsafe_
+            .cmp(&y_coord.get_counts(b).unsafe_),
+        SortKey::Unsafe => y_coord.get_counts(a).safe.cmp(&y_coord.get_counts(b).safe),
+        SortKey::Ratio => unsafe_share(y_coord, a).total_cmp(&unsafe_share(y_coord, b)),
+    }
+}
+
+/// Width of the bar of a 100% unsafe kind of items
+const BREAKDOWN_BAR_WIDTH: usize = 40;
+
+/// The unsafe share of each kind of item counted by geiger, one bar per kind
+fn breakdown(head: &CommitResult) -> String {
+    let c = &head.counters;
+    let kinds = [
+        ("functions", &c.functions),
+        ("methods", &c.methods),
+        ("expressions", &c.exprs),
+        ("impls", &c.item_impls),
+        ("traits", &c.item_traits),
+    ];
+
+    let mut buf = format!(
+        "{} {}\n",
+        head.short_oid(),
+        head.date.format(gix_date::time::format::SHORT)
+    );
+    for (kind, count) in kinds {
+        let total = count.unsafe_ + count.safe;
+        let share = if total == 0 {
+            0.0
+        } else {
+            count.unsafe_ as f64 / total as f64
+        };
+        // any unsafe at all gets a mark, so it doesn't look like none
+        let bar = match (share * BREAKDOWN_BAR_WIDTH as f64).round() as usize {
+            0 if count.unsafe_ > 0 => 1,
+            width => width,
+        };
+        buf.push_str(&format!(
+            "{:<12} {:>8} / {:<8} {:>5.1}% |{:<width$}|\n",
+            kind,
+            count.unsafe_,
+            total,
+            share * 100.0,
+            "#".repeat(bar),
+            width = BREAKDOWN_BAR_WIDTH,
+        ));
+    }
+    buf
+}
+
+/// The first line of the CSV output, followed by one [`csv_row`] per commit
+pub const CSV_HEADER: &str =
+    "ref,commit,date,matched_files,failed_files,unsafe,safe,forbids_unsafe,introduced_unsafe,introduced_safe\n";
+
+/// A line of the CSV output with the counts of `y_coord`, `name` being the analysed ref
+pub fn csv_row(name: &str, r: &CommitResult, y_coord: plot::YCoord) -> String {
+    let counts = y_coord.get_counts(r);
+    // the safe count holds the whole count for the kinds not split into safe and unsafe
+    let unsafe_ = if y_coord.is_split() {
+        counts.unsafe_.to_string()
+    } else {
+        String::new()
+    };
+    // of the files new in the commit, only for the kinds split into safe and unsafe
+    let (introduced_unsafe, introduced_safe) = match y_coord.get_introduced_counts(r) {
+        Some(counts) => (counts.unsafe_.to_string(), counts.safe.to_string()),
+        None => Default::default(),
+    };
+    format!(
+        "{},{},{},{},{},{},{},{},{},{}\n",
+        csv_field(name),
+        r.short_oid(),
+        r.date.format(gix_date::time::format::ISO8601_STRICT),
+        r.matched_files_count,
+        r.failed_files_count,
+        unsafe_,
+        counts.safe,
+        r.forbids_unsafe,
+        introduced_unsafe,
+        introduced_safe,
+    )
+}
+
+/// Quotes the field if it has a comma or a quote, ref names can
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
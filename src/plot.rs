@@ -1,187 +1,1829 @@
 use crate::analysis::CommitResult;
-use cargo_geiger_serde::Count;
-use chrono::{NaiveDateTime, TimeDelta};
+use cargo_geiger_serde::{Count, CounterBlock};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+#[cfg(feature = "cli")]
 use clap::ValueEnum;
+use gix_hash::ObjectId;
 use plotters::coord::ranged1d::ValueFormatter;
 use plotters::coord::types::RangedCoordu32;
 use plotters::coord::Shift;
 use plotters::prelude::*;
-use serde::Deserialize;
-use std::ops::Add;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::warn;
 
-fn convert_date(date: gix_date::Time) -> NaiveDateTime {
-    NaiveDateTime::UNIX_EPOCH.add(TimeDelta::seconds(date.seconds))
+pub mod palette;
+
+use palette::Palette;
+
+const BUNDLED_FONT: &[u8] = include_bytes!("../FiraSans-Regular.otf");
+
+/// Registers the font used for all chart labels under the `sans-serif` family.
+///
+/// If `custom_font` is given but can't be loaded, falls back to the bundled Fira Sans.
+pub fn register_font(custom_font: Option<&Path>) {
+    if let Some(path) = custom_font {
+        match std::fs::read(path) {
+            Ok(bytes) => {
+                // plotters wants the font data to live forever
+                let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+                match plotters::style::register_font("sans-serif", FontStyle::Normal, bytes) {
+                    Ok(()) => return,
+                    Err(_) => warn!(
+                        "Font {} is not a valid font file, falling back to the bundled one",
+                        path.display()
+                    ),
+                }
+            }
+            Err(e) => warn!(
+                "Could not read font {}: {}, falling back to the bundled one",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    plotters::style::register_font("sans-serif", FontStyle::Normal, BUNDLED_FONT)
+        .map_err(|_| "BUG: failed to register font")
+        .unwrap();
+}
+
+/// The timezone the dates on the X axis are shown in
+///
+/// ```
+/// use unsafe_track::plot::DateTimezone;
+///
+/// assert_eq!("utc".parse(), Ok(DateTimezone::Utc));
+/// assert_eq!("commit".parse(), Ok(DateTimezone::Commit));
+/// let fixed: DateTimezone = "+05:30".parse().unwrap();
+/// assert_eq!(fixed.to_string(), "+05:30");
+/// assert!("Europe/Berlin".parse::<DateTimezone>().is_err());
+/// ```
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DateTimezone {
+    #[default]
+    Utc,
+    /// The offset of each commit, so the commits are placed at the committer's local time
+    Commit,
+    /// A fixed offset from UTC, like `+02:00`
+    Fixed(FixedOffset),
+}
+
+impl FromStr for DateTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utc" => Ok(Self::Utc),
+            "commit" => Ok(Self::Commit),
+            offset => offset
+                .parse()
+                .map(Self::Fixed)
+                .map_err(|_| "expected utc, commit or an offset like +02:00".to_string()),
+        }
+    }
+}
+
+impl Display for DateTimezone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateTimezone::Utc => f.write_str("utc"),
+            DateTimezone::Commit => f.write_str("commit"),
+            DateTimezone::Fixed(offset) => offset.fmt(f),
+        }
+    }
+}
+
+impl TryFrom<String> for DateTimezone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl DateTimezone {
+    /// The date and the time of `time` in this timezone
+    pub fn localize(self, time: &gix_date::Time) -> DateTime<FixedOffset> {
+        let utc = DateTime::from_timestamp(time.seconds, 0).unwrap_or_default();
+        match self {
+            DateTimezone::Utc => utc.fixed_offset(),
+            DateTimezone::Fixed(offset) => utc.with_timezone(&offset),
+            // the local times of the commits are placed on a common axis, as if they were all in UTC
+            DateTimezone::Commit => (utc.naive_utc() + TimeDelta::seconds(time.offset.into()))
+                .and_utc()
+                .fixed_offset(),
+        }
+    }
+}
+
+impl From<DateTimezone> for String {
+    fn from(timezone: DateTimezone) -> Self {
+        timezone.to_string()
+    }
 }
 
 trait XCoordTrait {
     type Ranged: Ranged<ValueType = Self::Value> + ValueFormatter<Self::Value>;
     type Value: Copy + Ord + 'static;
 
-    const AXIS_DESCRIPTION: &'static str;
-
-    fn get_x_coord(commit: &CommitResult) -> Self::Value;
+    fn axis_description(&self) -> String;
+    fn get_x_coord(&self, commit: &CommitResult) -> Self::Value;
+    fn format_x(&self, value: &Self::Value) -> String;
     fn make_ranged(min: Self::Value, max: Self::Value) -> Self::Ranged;
+    /// Widens the range around a single commit, which would otherwise have no width to map to
+    fn widen_empty(min: Self::Value, max: Self::Value) -> (Self::Value, Self::Value);
+    /// The value of an end of [`PlotOptions::x_range`]
+    fn fixed_x(&self, value: i64) -> Self::Value;
 }
 
-pub struct XIsDate;
+pub struct XIsDate(DateTimezone);
 pub struct XIsIndex;
 
 impl XCoordTrait for XIsDate {
-    type Ranged = RangedDateTime<NaiveDateTime>;
-    type Value = NaiveDateTime;
+    type Ranged = RangedDateTime<DateTime<FixedOffset>>;
+    type Value = DateTime<FixedOffset>;
+
+    fn axis_description(&self) -> String {
+        match self.0 {
+            DateTimezone::Utc => "Date, UTC".to_string(),
+            DateTimezone::Commit => "Date, committer's local time".to_string(),
+            DateTimezone::Fixed(offset) => format!("Date, UTC{}", offset),
+        }
+    }
 
-    const AXIS_DESCRIPTION: &'static str = "Date";
+    fn get_x_coord(&self, commit: &CommitResult) -> Self::Value {
+        self.0.localize(&commit.date)
+    }
 
-    fn get_x_coord(commit: &CommitResult) -> Self::Value {
-        convert_date(commit.date)
+    fn format_x(&self, value: &Self::Value) -> String {
+        // the zone is in the axis description
+        format!("{:?}", value.naive_local())
     }
 
     fn make_ranged(min: Self::Value, max: Self::Value) -> Self::Ranged {
         RangedDateTime::from(min..max)
     }
+
+    fn widen_empty(min: Self::Value, max: Self::Value) -> (Self::Value, Self::Value) {
+        if min == max {
+            (min - TimeDelta::days(1), max + TimeDelta::days(1))
+        } else {
+            (min, max)
+        }
+    }
+
+    fn fixed_x(&self, value: i64) -> Self::Value {
+        self.0.localize(&gix_date::Time::new(value, 0))
+    }
 }
 
 impl XCoordTrait for XIsIndex {
     type Ranged = RangedCoordu32;
     type Value = u32;
 
-    const AXIS_DESCRIPTION: &'static str = "Commit Index";
+    fn axis_description(&self) -> String {
+        "Commit Index".to_string()
+    }
 
-    fn get_x_coord(commit: &CommitResult) -> Self::Value {
+    fn get_x_coord(&self, commit: &CommitResult) -> Self::Value {
         commit.index
     }
 
+    fn format_x(&self, value: &Self::Value) -> String {
+        value.to_string()
+    }
+
     fn make_ranged(min: Self::Value, max: Self::Value) -> Self::Ranged {
         RangedCoordu32::from(min..max)
     }
+
+    fn widen_empty(min: Self::Value, max: Self::Value) -> (Self::Value, Self::Value) {
+        if min == max {
+            (min.saturating_sub(1), max + 1)
+        } else {
+            (min, max)
+        }
+    }
+
+    fn fixed_x(&self, value: i64) -> Self::Value {
+        value.clamp(0, u32::MAX.into()) as u32
+    }
 }
 
-#[derive(Copy, Clone, Default, Debug, Deserialize, ValueEnum)]
+/// What to put on the X axis of the chart
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
 pub enum XCoord {
     #[default]
     Index,
     Date,
 }
 
-#[derive(Copy, Clone, Default, Debug, Deserialize, ValueEnum)]
+/// What to count on the Y axis of the chart
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
 pub enum YCoord {
+    /// Functions and methods, like `Counters` of both
     #[default]
     Functions,
+    /// Expressions, like `Counters` of them alone
     Expressions,
+    /// Lines of code, plotted as a single series
+    LinesOfCode,
+    /// Functions declared in `extern` blocks, plotted as a single series
+    FfiFunctions,
+    /// `unsafe` blocks, plotted as a single series
+    UnsafeBlocks,
+    /// `unsafe fn` definitions, plotted as a single series
+    UnsafeFunctions,
+    /// `unsafe impl`s, plotted as a single series
+    UnsafeImpls,
+    /// Functions and methods in the files new in each commit, see
+    /// [`CommitResult::introduced_counters`]. Plotted as points, the commits don't add up to a
+    /// line.
+    Introduced,
+    /// The sum of the chosen kinds of geiger's counters, see [`YCoord::from_count_kinds`]
+    #[cfg_attr(feature = "cli", value(skip))]
+    Counters(CountKinds),
 }
 
 impl YCoord {
+    /// [`YCoord::Functions`] or [`YCoord::Expressions`] if `kinds` are what they count, so the
+    /// charts of the same counts look the same, [`YCoord::Counters`] otherwise
+    ///
+    /// ```
+    /// use unsafe_track::plot::{CountKind, CountKinds, YCoord};
+    ///
+    /// let kinds = CountKinds::from_iter([CountKind::Methods, CountKind::Functions]);
+    /// assert_eq!(YCoord::from_count_kinds(kinds), YCoord::Functions);
+    /// let kinds = CountKinds::from_iter([CountKind::Exprs, CountKind::ItemImpls]);
+    /// assert_eq!(YCoord::from_count_kinds(kinds), YCoord::Counters(kinds));
+    /// ```
+    pub fn from_count_kinds(kinds: CountKinds) -> Self {
+        [YCoord::Functions, YCoord::Expressions]
+            .into_iter()
+            .find(|y_coord| y_coord.count_kinds() == Some(kinds))
+            .unwrap_or(YCoord::Counters(kinds))
+    }
+
+    /// The counters summed into the counts, for the kinds split by safety
+    pub fn count_kinds(&self) -> Option<CountKinds> {
+        match self {
+            YCoord::Functions => Some(CountKinds::from_iter([
+                CountKind::Functions,
+                CountKind::Methods,
+            ])),
+            YCoord::Expressions => Some(CountKinds::from_iter([CountKind::Exprs])),
+            YCoord::Introduced => YCoord::Functions.count_kinds(),
+            YCoord::Counters(kinds) => Some(*kinds),
+            _ => None,
+        }
+    }
+
+    /// Safe and unsafe counts of the selected kind in the commit
+    ///
+    /// The kinds that are not split by safety (see [`YCoord::is_split`]) report all of their count as `safe`.
     pub fn get_counts(&self, commit: &CommitResult) -> Count {
+        let single = |count| Count {
+            safe: count,
+            unsafe_: 0,
+        };
+
         match self {
-            YCoord::Functions => {
-                commit.counters.functions.clone() + commit.counters.methods.clone()
+            YCoord::LinesOfCode => single(commit.total_loc),
+            YCoord::FfiFunctions => single(commit.extra.ffi_functions),
+            YCoord::UnsafeBlocks => single(commit.extra.unsafe_blocks),
+            YCoord::UnsafeFunctions => single(commit.extra.unsafe_functions),
+            YCoord::UnsafeImpls => single(commit.extra.unsafe_impls),
+            YCoord::Functions | YCoord::Expressions | YCoord::Counters(_) => {
+                let kinds = self.count_kinds().expect("BUG: counters without kinds");
+                kinds.sum(&commit.counters)
+            }
+            YCoord::Introduced => {
+                let kinds = self.count_kinds().expect("BUG: counters without kinds");
+                kinds.sum(&commit.introduced_counters)
             }
-            YCoord::Expressions => commit.counters.exprs.clone(),
         }
     }
+
+    /// Safe and unsafe counts of the selected kinds in the files new in the commit, `None` for the
+    /// kinds not split by safety
+    pub fn get_introduced_counts(&self, commit: &CommitResult) -> Option<Count> {
+        self.count_kinds()
+            .map(|kinds| kinds.sum(&commit.introduced_counters))
+    }
+
+    /// Whether the commits are drawn as points rather than as a line, when their counts don't
+    /// follow from the ones of the commits before
+    pub fn is_points(&self) -> bool {
+        *self == YCoord::Introduced
+    }
+
+    /// Whether the counts have separate safe and unsafe parts
+    pub fn is_split(&self) -> bool {
+        self.count_kinds().is_some()
+    }
+
+    /// Names the metric in the legend when several are drawn
+    pub fn label(&self) -> Cow<'static, str> {
+        Cow::Borrowed(match self {
+            YCoord::Functions => "functions",
+            YCoord::Expressions => "expressions",
+            YCoord::LinesOfCode => "lines of code",
+            YCoord::FfiFunctions => "FFI functions",
+            YCoord::UnsafeBlocks => "unsafe blocks",
+            YCoord::UnsafeFunctions => "unsafe functions",
+            YCoord::UnsafeImpls => "unsafe impls",
+            YCoord::Introduced => "new functions",
+            YCoord::Counters(kinds) => return Cow::Owned(kinds.label()),
+        })
+    }
+}
+
+/// One of the counters geiger keeps of each file, split into safe and unsafe counts
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum CountKind {
+    /// Free functions
+    Functions,
+    /// Functions in `impl` blocks
+    Methods,
+    /// Expressions
+    Exprs,
+    /// `impl` blocks
+    ItemImpls,
+    /// Trait definitions
+    ItemTraits,
+}
+
+impl CountKind {
+    const ALL: [CountKind; 5] = [
+        CountKind::Functions,
+        CountKind::Methods,
+        CountKind::Exprs,
+        CountKind::ItemImpls,
+        CountKind::ItemTraits,
+    ];
+
+    fn get(self, counters: &CounterBlock) -> &Count {
+        match self {
+            CountKind::Functions => &counters.functions,
+            CountKind::Methods => &counters.methods,
+            CountKind::Exprs => &counters.exprs,
+            CountKind::ItemImpls => &counters.item_impls,
+            CountKind::ItemTraits => &counters.item_traits,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            CountKind::Functions => "functions",
+            CountKind::Methods => "methods",
+            CountKind::Exprs => "expressions",
+            CountKind::ItemImpls => "impls",
+            CountKind::ItemTraits => "traits",
+        }
+    }
+}
+
+/// A set of [`CountKind`]s, which [`YCoord::Counters`] plots the sum of
+///
+/// Serialized as the list of the kinds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<CountKind>", into = "Vec<CountKind>")]
+pub struct CountKinds(u8);
+
+impl CountKinds {
+    pub fn contains(self, kind: CountKind) -> bool {
+        self.0 & (1 << kind as u8) != 0
+    }
+
+    /// The kinds in the set, in the order of [`CountKind`]
+    pub fn iter(self) -> impl Iterator<Item = CountKind> {
+        CountKind::ALL
+            .into_iter()
+            .filter(move |&kind| self.contains(kind))
+    }
+
+    fn sum(self, counters: &CounterBlock) -> Count {
+        self.iter()
+            .map(|kind| kind.get(counters).clone())
+            .fold(Count::default(), |sum, count| sum + count)
+    }
+
+    /// Like `functions + methods`
+    fn label(self) -> String {
+        self.iter()
+            .map(CountKind::label)
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+}
+
+impl FromIterator<CountKind> for CountKinds {
+    fn from_iter<I: IntoIterator<Item = CountKind>>(kinds: I) -> Self {
+        CountKinds(
+            kinds
+                .into_iter()
+                .fold(0, |bits, kind| bits | 1 << kind as u8),
+        )
+    }
+}
+
+impl From<Vec<CountKind>> for CountKinds {
+    fn from(kinds: Vec<CountKind>) -> Self {
+        kinds.into_iter().collect()
+    }
+}
+
+impl From<CountKinds> for Vec<CountKind> {
+    fn from(kinds: CountKinds) -> Self {
+        kinds.iter().collect()
+    }
+}
+
+/// How the counts of the commits are turned into the plotted values
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum PlotMode {
+    /// The counts as they are in each commit
+    #[default]
+    Absolute,
+    /// The change of the counts relative to the previous commit
+    Delta,
+}
+
+impl PlotMode {
+    /// Computes the plotted `(unsafe, safe)` values of the date-sorted commits
+    pub fn values(&self, results: &[CommitResult], y_coord: YCoord) -> Vec<(i64, i64)> {
+        let counts = results.iter().map(|c| {
+            let c = y_coord.get_counts(c);
+            (c.unsafe_ as i64, c.safe as i64)
+        });
+
+        match self {
+            PlotMode::Absolute => counts.collect(),
+            PlotMode::Delta => {
+                // the first commit is compared to an empty repository
+                let mut previous = (0, 0);
+                counts
+                    .map(|(unsafe_, safe)| {
+                        let delta = (unsafe_ - previous.0, safe - previous.1);
+                        previous = (unsafe_, safe);
+                        delta
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// How the chart is drawn
+///
+/// ```
+/// # use unsafe_track::analysis::CommitResult;
+/// use unsafe_track::plot::{plot_results_svg, register_font, PlotOptions, PlotStyle};
+///
+/// register_font(None);
+/// let commit = |index: u32, unsafe_functions| {
+///     let mut commit = CommitResult {
+///         oid: gix_hash::ObjectId::null(gix_hash::Kind::Sha1),
+///         index,
+///         date: gix_date::Time::new(1_700_000_000 + index as i64, 0),
+///         matched_files_count: 1,
+///         failed_files_count: 0,
+///         counters: Default::default(),
+///         introduced_counters: Default::default(),
+///         total_loc: 0,
+///         extra: Default::default(),
+///         forbids_unsafe: false,
+///         abbrev_len: 7,
+///     };
+///     commit.counters.functions.unsafe_ = unsafe_functions;
+///     commit
+/// };
+/// let mut options = PlotOptions::default();
+/// options.style = PlotStyle::Sparkline;
+///
+/// let rising = plot_results_svg(&[commit(0, 1), commit(1, 3)], None, &options)?;
+/// assert!(rising.contains(r#"width="300" height="60""#));
+/// assert!(!rising.contains("<text"));
+/// // the unsafe count went up, the line is red
+/// assert!(rising.contains("#FF0000"));
+/// let falling = plot_results_svg(&[commit(0, 3), commit(1, 1)], None, &options)?;
+/// assert!(falling.contains("#00FF00"));
+///
+/// // without any results or with a single one, the line is flat
+/// for results in [&[][..], &[commit(0, 2)]] {
+///     let flat = plot_results_svg(results, None, &options)?;
+///     assert!(flat.contains("<polyline"));
+/// }
+/// # Ok::<(), unsafe_track::plot::PlotError>(())
+/// ```
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum PlotStyle {
+    /// With the axes, the legend and the caption
+    #[default]
+    Chart,
+    /// A 300x60 line of the unsafe counts of the first metric alone, to embed in a table or a
+    /// dashboard. The commits are evenly spaced, and the line is in the unsafe color if the count
+    /// went up and in the safe one otherwise.
+    Sparkline,
+    /// Like `Sparkline`, with the area under the line filled
+    SparklineArea,
+}
+
+impl PlotStyle {
+    pub fn is_sparkline(self) -> bool {
+        matches!(self, PlotStyle::Sparkline | PlotStyle::SparklineArea)
+    }
+}
+
+/// Lines beyond this many are drawn, but left out of the legend
+const MAX_LEGEND_ENTRIES: usize = 8;
+
+/// The largest value of each line when several metrics are drawn, a tenth of a percent each
+const NORMALIZED_MAX: i64 = 1000;
+
+/// How much taller the image gets for every chart past the first with [`PlotOptions::subplots`]
+const SUBPLOT_HEIGHT: u32 = 250;
+
+/// The lines of more commits don't get [`PlotOptions::markers`], which would blur into one
+pub const MAX_MARKED_COMMITS: usize = 200;
+
+/// The largest accepted moving average window, in commits
+pub const MAX_SMOOTHING_WINDOW: u32 = 200;
+
+/// The accepted [`PlotOptions::width`] and [`PlotOptions::height`], in pixels
+pub const IMAGE_SIZE_RANGE: RangeInclusive<u32> = 50..=4000;
+
+/// Parameters of the chart
+///
+/// More options can be added, so outside of this crate it's built from [`PlotOptions::default`].
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PlotOptions {
+    pub x_coord: XCoord,
+    pub y_coord: YCoord,
+    /// More metrics drawn on the same chart as `y_coord`. As their magnitudes differ, each line is
+    /// then scaled to its own largest value on a percentage axis, and the threshold isn't drawn.
+    pub extra_y_coords: Vec<YCoord>,
+    /// Draw `y_coord` and each of the `extra_y_coords` as a chart of its own instead, stacked from
+    /// top to bottom and sharing the X axis. The threshold is drawn on the first one.
+    #[serde(default)]
+    pub subplots: bool,
+    /// Leave out the safe counts of the metrics split into safe and unsafe
+    pub unsafe_only: bool,
+    /// Also draw the sum of the safe and the unsafe counts in a muted color, telling the unsafe
+    /// code growing apart from all of the code growing. Only applies to the metrics split into
+    /// safe and unsafe.
+    #[serde(default)]
+    pub show_total: bool,
+    pub mode: PlotMode,
+    /// Window of the moving average drawn over the series, in commits
+    pub smooth: Option<u32>,
+    /// Draw only the moving average, without the raw series. Has no effect without `smooth`.
+    pub smooth_only: bool,
+    /// Draw the unsafe share of the counts as a dashed line on a secondary 0-100% axis.
+    /// Only applies to the absolute counts split into safe and unsafe.
+    pub ratio_axis: bool,
+    /// Draw the number of files that could not be analysed as a dashed line on a secondary axis.
+    /// Left out if no commit has any. Drawn as their share of the matching files when `ratio_axis` is drawn.
+    pub show_failures: bool,
+    /// Draw a dot at each commit of the lines, so that the commits can be told apart from the line
+    /// between them. Left out of the lines of more than [`MAX_MARKED_COMMITS`] commits.
+    #[serde(default)]
+    pub markers: bool,
+    /// Draw the lines as steps, holding the value of each commit until the next one, instead of
+    /// joining the commits with straight lines. The counts only change at the commits, the slope
+    /// between them is made up.
+    #[serde(default)]
+    pub step: bool,
+    /// Draw a dashed horizontal line at this count, like the budget of a CI check.
+    /// Clamped to the plotted range, and not drawn with `extra_y_coords`.
+    pub threshold: Option<i64>,
+    /// Draw a dashed horizontal line at this unsafe share, in percent. Only drawn with the ratio axis.
+    pub ratio_threshold: Option<f64>,
+    pub palette: Palette,
+    /// The timezone of the dates on the X axis, when it has the dates
+    pub timezone: DateTimezone,
+    /// Only the first history and the first metric are drawn as a sparkline, which leaves out the
+    /// other options but `mode`, `palette` and `transparent`
+    #[serde(default)]
+    pub style: PlotStyle,
+    /// The X axis spans this range instead of the one of the commits, in commit indices or in Unix
+    /// timestamps depending on `x_coord`. The same range given to several charts lines them up.
+    #[serde(default)]
+    pub x_range: Option<Range<i64>>,
+    /// The Y axis spans this range instead of the one of the plotted values, in percent when several
+    /// metrics are each scaled to their largest value
+    #[serde(default)]
+    pub y_range: Option<Range<i64>>,
+    /// Leave the background of the drawing area as it is instead of filling it white, like for a
+    /// panel of a larger figure
+    #[serde(default)]
+    pub transparent: bool,
+    /// Leave out the box labeling the lines
+    #[serde(default)]
+    pub hide_legend: bool,
+    /// The width of the image instead of the one of the `style`, in pixels
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// The height of the image instead of the one of the `style` and the `subplots`, in pixels
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+impl PlotOptions {
+    /// Fills the whole `area` white, unless the chart is [`PlotOptions::transparent`]
+    fn fill_background<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+    ) -> Result<(), PlotError> {
+        if !self.transparent {
+            area.fill(&WHITE)?;
+        }
+        Ok(())
+    }
+}
+
+/// `range` with its ends in order, widened by `widen` if they are the same
+fn fixed_range<T: Copy + Ord>(range: &Range<T>, widen: impl Fn(T, T) -> (T, T)) -> (T, T) {
+    let (start, end) = if range.start <= range.end {
+        (range.start, range.end)
+    } else {
+        (range.end, range.start)
+    };
+    widen(start, end)
+}
+
+/// What the chart was made from, drawn as a caption above it and embedded into the SVG images as a
+/// [`ChartProvenance`], so a saved chart can be traced back
+#[derive(Clone, Debug)]
+pub struct ChartMetadata {
+    /// URL of the analysed repository
+    pub repository: String,
+    /// The analysed ref, or the compared ones
+    pub ref_name: String,
+    /// Regex of the analysed paths
+    pub include: String,
+    /// Regex of the skipped paths
+    pub exclude: Option<String>,
+    /// The commit the analysed history ends at, the first one of the compared refs
+    pub tip: Option<ObjectId>,
+    /// When the chart was made
+    pub generated_at: DateTime<Utc>,
+    /// Whether the local macros were expanded, which makes the counts approximate
+    pub expanded_local_macros: bool,
+}
+
+/// Longer filter patterns are cut short in the caption, the whole line is cut to the chart width too
+const MAX_CAPTION_PATTERN_CHARS: usize = 40;
+const CAPTION_HEIGHT: u32 = 20;
+
+impl ChartMetadata {
+    /// Describes a chart made today
+    pub fn new(repository: &str, ref_name: &str, include: &str, exclude: Option<&str>) -> Self {
+        Self {
+            repository: repository.to_string(),
+            ref_name: ref_name.to_string(),
+            include: include.to_string(),
+            exclude: exclude.map(str::to_string),
+            tip: None,
+            generated_at: Utc::now(),
+            expanded_local_macros: false,
+        }
+    }
+
+    /// The line of the caption, like `https://github.com/owner/repo @ HEAD · include \.rs$ · 2024-01-01`
+    ///
+    /// ```
+    /// use unsafe_track::plot::ChartMetadata;
+    ///
+    /// let metadata = ChartMetadata {
+    ///     exclude: Some("x".repeat(100)),
+    ///     generated_at: "2024-01-01T12:00:00Z".parse().unwrap(),
+    ///     ..ChartMetadata::new("https://github.com/owner/repo", "HEAD", r"\.rs$", None)
+    /// };
+    /// let caption = metadata.caption();
+    /// assert!(caption.starts_with(r"https://github.com/owner/repo @ HEAD · include \.rs$ · exclude xxx"));
+    /// assert!(caption.ends_with("x… · 2024-01-01"));
+    /// ```
+    pub fn caption(&self) -> String {
+        let mut caption = format!(
+            "{} @ {} · include {}",
+            self.repository,
+            self.ref_name,
+            truncate(&self.include, MAX_CAPTION_PATTERN_CHARS)
+        );
+        if let Some(exclude) = &self.exclude {
+            caption.push_str(" · exclude ");
+            caption.push_str(&truncate(exclude, MAX_CAPTION_PATTERN_CHARS));
+        }
+        if self.expanded_local_macros {
+            caption.push_str(" · local macros expanded (approximate)");
+        }
+        caption.push_str(&format!(" · {}", self.generated_at.date_naive()));
+        caption
+    }
+}
+
+/// What an SVG chart was made from, embedded into it by [`plot_results_svg`] and
+/// [`plot_comparison_svg`] as a `<metadata>` element, and read back by [`read_provenance`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChartProvenance {
+    /// Version of unsafe-track that made the chart
+    pub tool_version: String,
+    pub repository: String,
+    #[serde(rename = "ref")]
+    pub ref_name: String,
+    /// Full id of [`ChartMetadata::tip`]
+    #[serde(default)]
+    pub tip: Option<String>,
+    pub include: String,
+    #[serde(default)]
+    pub exclude: Option<String>,
+    #[serde(default)]
+    pub expanded_local_macros: bool,
+    pub generated_at: DateTime<Utc>,
+    pub options: PlotOptions,
+}
+
+/// Marks the `<metadata>` element with the [`ChartProvenance`], an SVG can have others
+const PROVENANCE_START: &str = r#"<metadata id="unsafe-track">"#;
+const PROVENANCE_END: &str = "</metadata>";
+
+/// Puts the provenance right after the opening `<svg>` tag of the chart
+fn embed_provenance(svg: &mut String, metadata: &ChartMetadata, options: &PlotOptions) {
+    let provenance = ChartProvenance {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        repository: metadata.repository.clone(),
+        ref_name: metadata.ref_name.clone(),
+        tip: metadata.tip.map(|tip| tip.to_string()),
+        include: metadata.include.clone(),
+        exclude: metadata.exclude.clone(),
+        expanded_local_macros: metadata.expanded_local_macros,
+        generated_at: metadata.generated_at,
+        options: options.clone(),
+    };
+    let json = serde_json::to_string(&provenance).expect("BUG: the provenance is serializable");
+    // the JSON is the text content of the element
+    let text = json
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let Some(tag_end) = svg
+        .find("<svg")
+        .and_then(|start| Some(start + svg[start..].find('>')? + 1))
+    else {
+        warn!("No <svg> tag in the chart, leaving out its provenance");
+        return;
+    };
+    svg.insert_str(
+        tag_end,
+        &format!("\n{}{}{}", PROVENANCE_START, text, PROVENANCE_END),
+    );
+}
+
+/// Reads the [`ChartProvenance`] of a chart made by [`plot_results_svg`] or
+/// [`plot_comparison_svg`], `None` if it has none, like the charts made without a caption
+///
+/// ```
+/// # use unsafe_track::analysis::CommitResult;
+/// use unsafe_track::plot::{
+///     plot_results_svg, read_provenance, register_font, ChartMetadata, PlotOptions, YCoord,
+/// };
+///
+/// register_font(None);
+/// let commit = CommitResult {
+///     oid: gix_hash::ObjectId::null(gix_hash::Kind::Sha1),
+///     index: 0,
+///     date: gix_date::Time::new(1_700_000_000, 0),
+///     matched_files_count: 10,
+///     failed_files_count: 0,
+///     counters: Default::default(),
+///     introduced_counters: Default::default(),
+///     total_loc: 0,
+///     extra: Default::default(),
+///     forbids_unsafe: false,
+///     abbrev_len: 7,
+/// };
+/// let metadata = ChartMetadata {
+///     tip: Some(commit.oid),
+///     ..ChartMetadata::new("https://github.com/owner/repo", "main", r"<\.rs$", None)
+/// };
+/// let mut options = PlotOptions::default();
+/// options.y_coord = YCoord::Expressions;
+///
+/// let svg = plot_results_svg(&[commit.clone()], Some(&metadata), &options)?;
+/// let provenance = read_provenance(&svg).unwrap().expect("the chart has a caption");
+/// assert_eq!(provenance.repository, "https://github.com/owner/repo");
+/// assert_eq!(provenance.ref_name, "main");
+/// assert_eq!(provenance.tip, Some(commit.oid.to_string()));
+/// assert_eq!(provenance.include, r"<\.rs$");
+/// assert_eq!(provenance.generated_at, metadata.generated_at);
+/// assert_eq!(provenance.options.y_coord, YCoord::Expressions);
+///
+/// let svg = plot_results_svg(&[commit], None, &options)?;
+/// assert!(read_provenance(&svg).unwrap().is_none());
+/// # Ok::<(), unsafe_track::plot::PlotError>(())
+/// ```
+pub fn read_provenance(svg: &str) -> Result<Option<ChartProvenance>, serde_json::Error> {
+    let Some(start) = svg.find(PROVENANCE_START) else {
+        return Ok(None);
+    };
+    let text = &svg[start + PROVENANCE_START.len()..];
+    let text = &text[..text.find(PROVENANCE_END).unwrap_or(text.len())];
+    let json = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    serde_json::from_str(&json).map(Some)
+}
+
+/// Cuts `text` to `max_chars` characters, ending with an ellipsis if anything was cut
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars.saturating_sub(1)) {
+        Some((end, _)) if text.chars().count() > max_chars => format!("{}…", &text[..end]),
+        _ => text.to_string(),
+    }
+}
+
+fn draw_caption<DB>(
+    metadata: &ChartMetadata,
+    area: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    let style = ("sans-serif", 12)
+        .into_font()
+        .color(&BLACK.mix(0.6))
+        .pos(Pos::new(HPos::Left, VPos::Center));
+    let margin = 5;
+    let max_width = area.dim_in_pixel().0.saturating_sub(2 * margin as u32);
+
+    // the repository and the ref alone can be too long for a narrow chart
+    let caption = metadata.caption();
+    let mut chars = caption.chars().count();
+    let mut text = caption.clone();
+    while chars > 1 && area.estimate_text_size(&text, &style)?.0 > max_width {
+        chars -= 1;
+        text = truncate(&caption, chars);
+    }
+
+    area.draw_text(&text, &style, (margin, CAPTION_HEIGHT as i32 / 2))?;
+    Ok(())
+}
+
+/// Simple moving average of `values` over `window` consecutive values, rounded to the nearest integer
+///
+/// The first `window - 1` values are averaged over the shorter window of the values available so far.
+/// Halves are rounded away from zero, so the averages of negative deltas mirror the positive ones.
+///
+/// ```
+/// use unsafe_track::plot::moving_average;
+///
+/// assert_eq!(moving_average(&[2, 4, 6, 8], 3), vec![2, 3, 4, 6]);
+/// assert_eq!(moving_average(&[3, -1, 7], 1), vec![3, -1, 7]);
+/// assert_eq!(moving_average(&[1, 2, 6], 10), vec![1, 2, 3]);
+/// assert_eq!(moving_average(&[-1, -2, 1, 2], 2), vec![-1, -2, -1, 2]);
+/// ```
+pub fn moving_average(values: &[i64], window: usize) -> Vec<i64> {
+    let window = window.max(1);
+    let mut sum = 0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            sum += value;
+            if i >= window {
+                sum -= values[i - window];
+            }
+            let len = (i + 1).min(window) as f64;
+            (sum as f64 / len).round() as i64
+        })
+        .collect()
+}
+
+/// An error that prevented the chart from being drawn
+#[derive(Error, Debug)]
+pub enum PlotError {
+    #[error("Drawing failed: {0}")]
+    Drawing(String),
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        Self::Drawing(e.to_string())
+    }
 }
 
-pub fn plot_results_svg(results: &[CommitResult], x_coord: XCoord, y_coord: YCoord) -> String {
+/// Renders the chart of the results into an 800x400 SVG image, taller with [`PlotOptions::subplots`]
+///
+/// ```
+/// # use unsafe_track::analysis::CommitResult;
+/// use unsafe_track::plot::{
+///     plot_results_svg, register_font, PlotOptions, XCoord, YCoord, MAX_MARKED_COMMITS,
+/// };
+///
+/// register_font(None);
+/// let commit = |index: u32, failed_files_count| CommitResult {
+///     oid: gix_hash::ObjectId::null(gix_hash::Kind::Sha1),
+///     index,
+///     date: gix_date::Time::new(1_700_000_000 + index as i64, 0),
+///     matched_files_count: 10,
+///     failed_files_count,
+///     counters: Default::default(),
+///     introduced_counters: Default::default(),
+///     total_loc: 0,
+///     extra: Default::default(),
+///     forbids_unsafe: false,
+///     abbrev_len: 7,
+/// };
+/// let mut options = PlotOptions::default();
+/// options.show_failures = true;
+///
+/// // the failures get a series only when there are some
+/// let clean = plot_results_svg(&[commit(0, 0), commit(1, 0)], None, &options)?;
+/// assert!(!clean.contains("failed files"));
+/// let failing = plot_results_svg(&[commit(0, 0), commit(1, 2)], None, &options)?;
+/// assert!(failing.contains("failed files"));
+///
+/// // the total is drawn next to the unsafe and the safe counts on request
+/// let total_label = "\ntotal\n";
+/// assert!(!clean.contains(total_label));
+/// let mut options = PlotOptions::default();
+/// options.show_total = true;
+/// let with_total = plot_results_svg(&[commit(0, 0), commit(1, 0)], None, &options)?;
+/// assert!(with_total.contains(total_label));
+///
+/// // each metric gets a chart of its own, the image grows to fit them
+/// let mut options = PlotOptions::default();
+/// options.extra_y_coords = vec![YCoord::Expressions];
+/// options.subplots = true;
+/// let stacked = plot_results_svg(&[commit(0, 0), commit(1, 0)], None, &options)?;
+/// assert!(stacked.contains(r#"height="650""#));
+/// assert!(stacked.contains("Function count") && stacked.contains("Expression count"));
+///
+/// // the size given wins over the one of the subplots
+/// options.width = Some(1200);
+/// options.height = Some(500);
+/// let sized = plot_results_svg(&[commit(0, 0), commit(1, 0)], None, &options)?;
+/// assert!(sized.contains(r#"width="1200" height="500""#));
+///
+/// // the commits are marked on request, but not on the lines too long to tell them apart
+/// let mut options = PlotOptions::default();
+/// options.markers = true;
+/// let marked = plot_results_svg(&[commit(0, 0), commit(1, 0)], None, &options)?;
+/// assert_eq!(marked.matches("<circle").count(), 4);
+/// let dense = (0..=MAX_MARKED_COMMITS as u32).map(|i| commit(i, 0)).collect::<Vec<_>>();
+/// assert!(!plot_results_svg(&dense, None, &options)?.contains("<circle"));
+///
+/// // a single commit has no range to span, it's marked in the middle of a widened one
+/// for x_coord in [XCoord::Index, XCoord::Date] {
+///     let mut options = PlotOptions::default();
+///     options.x_coord = x_coord;
+///     let single = plot_results_svg(&[commit(0, 0)], None, &options)?;
+///     assert!(single.contains("<circle"));
+/// }
+/// # Ok::<(), unsafe_track::plot::PlotError>(())
+/// ```
+pub fn plot_results_svg(
+    results: &[CommitResult],
+    metadata: Option<&ChartMetadata>,
+    options: &PlotOptions,
+) -> Result<String, PlotError> {
     let mut buf = String::new();
-    let root = SVGBackend::with_string(&mut buf, (800, 400)).into_drawing_area();
-    plot_results(results, x_coord, y_coord, &root);
+    let root = SVGBackend::with_string(&mut buf, image_size(options)).into_drawing_area();
+    plot_results(results, metadata, options, &root)?;
     drop(root);
-    buf
+    if let Some(metadata) = metadata {
+        embed_provenance(&mut buf, metadata, options);
+    }
+    Ok(buf)
 }
 
-#[tracing::instrument(skip(results, root), fields(result_count = results.len()))]
+/// Renders the chart of the results into the drawing area, using all of it
+///
+/// With `metadata`, a caption describing what the results were made from is drawn above the chart.
+/// If there are no results, a placeholder with a message is drawn instead.
+#[tracing::instrument(skip(results, metadata, root), fields(result_count = results.len()))]
 pub fn plot_results<DB>(
     results: &[CommitResult],
-    x_coord: XCoord,
-    y_coord: YCoord,
+    metadata: Option<&ChartMetadata>,
+    options: &PlotOptions,
+    root: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    // neither the sparkline nor the placeholder has room for it
+    let metadata = metadata.filter(|_| !options.style.is_sparkline() && !results.is_empty());
+    let area = split_caption(metadata, options, root)?;
+    plot_results_into(results, options, &area)
+}
+
+/// Renders the chart of the results into the drawing area, without a caption, like a panel of a
+/// larger figure
+///
+/// The panels given the same [`PlotOptions::x_range`] and [`PlotOptions::y_range`] share their
+/// axes, the ranges of the others fit their results. A range whose ends are the same is widened
+/// like the one of a single commit.
+///
+/// ```
+/// # use unsafe_track::analysis::CommitResult;
+/// use plotters::prelude::*;
+/// use unsafe_track::plot::{plot_results_into, register_font, PlotOptions};
+///
+/// register_font(None);
+/// let commit = |index: u32, unsafe_| {
+///     let mut counters = cargo_geiger_serde::CounterBlock::default();
+///     counters.functions.unsafe_ = unsafe_;
+///     CommitResult {
+///         oid: gix_hash::ObjectId::from([index as u8; 20]),
+///         index,
+///         date: gix_date::Time::new(1_700_000_000 + index as i64 * 86_400, 0),
+///         matched_files_count: 1,
+///         failed_files_count: 0,
+///         counters,
+///         introduced_counters: Default::default(),
+///         total_loc: 0,
+///         extra: Default::default(),
+///         forbids_unsafe: false,
+///         abbrev_len: 7,
+///     }
+/// };
+/// let small = [commit(0, 1), commit(1, 2)];
+/// let large = [commit(0, 10), commit(1, 500)];
+/// let render = |results: &[CommitResult], options: &PlotOptions| {
+///     let mut svg = String::new();
+///     let root = SVGBackend::with_string(&mut svg, (400, 200)).into_drawing_area();
+///     plot_results_into(results, options, &root)?;
+///     drop(root);
+///     Ok::<_, unsafe_track::plot::PlotError>(svg)
+/// };
+/// let has_label = |svg: &str, label| svg.contains(&format!(">\n{}\n<", label));
+///
+/// // auto-ranged, each panel labels its own Y axis
+/// let auto = PlotOptions::default();
+/// assert!(!has_label(&render(&small, &auto)?, 400));
+/// assert!(has_label(&render(&large, &auto)?, 400));
+/// let background = "fill=\"#FFFFFF\" stroke=\"none\"";
+/// assert!(render(&small, &auto)?.contains(background));
+/// assert!(render(&small, &auto)?.contains("unsafe"));
+///
+/// // with a fixed range the panels have the same axis, the values beyond it stay at its edge
+/// let mut shared = PlotOptions::default();
+/// shared.y_range = Some(0..1000);
+/// shared.hide_legend = true;
+/// shared.transparent = true;
+/// for results in [&small, &large] {
+///     let svg = render(results, &shared)?;
+///     assert!(has_label(&svg, 800));
+///     assert!(!svg.contains("unsafe"), "no legend");
+///     assert!(!svg.contains(background), "no background");
+/// }
+/// let mut clipped = shared.clone();
+/// clipped.y_range = Some(0..5);
+/// assert!(has_label(&render(&large, &clipped)?, 4));
+///
+/// // the ends in any order, and the same ones, still make a range
+/// shared.y_range = Some(1000..0);
+/// assert!(has_label(&render(&small, &shared)?, 800));
+/// shared.y_range = Some(3..3);
+/// shared.x_range = Some(1..1);
+/// render(&small, &shared)?;
+/// # Ok::<(), unsafe_track::plot::PlotError>(())
+/// ```
+pub fn plot_results_into<DB>(
+    results: &[CommitResult],
+    options: &PlotOptions,
+    area: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    if options.style.is_sparkline() {
+        return plot_sparkline(results, options, area);
+    }
+    if results.is_empty() {
+        return plot_placeholder("No matching Rust files", options, area);
+    }
+
+    plot_histories(&[("", results)], options, area)
+}
+
+/// Renders the chart comparing several histories into an SVG image sized like [`plot_results_svg`]
+///
+/// Each history is a labeled line (or a pair of lines, for the unsafe and the safe counts), like
+/// the branches of a repository. The commits a history shares with the ones before it are drawn
+/// once, its lines start from the last of them, where it forks off.
+///
+/// ```
+/// # use unsafe_track::analysis::CommitResult;
+/// use unsafe_track::plot::{plot_comparison_svg, register_font, PlotOptions};
+///
+/// register_font(None);
+/// let commit = |index: u32| CommitResult {
+///     oid: gix_hash::ObjectId::from([index as u8; 20]),
+///     index,
+///     date: gix_date::Time::new(1_700_000_000 + index as i64, 0),
+///     matched_files_count: 10,
+///     failed_files_count: 0,
+///     counters: Default::default(),
+///     introduced_counters: Default::default(),
+///     total_loc: 0,
+///     extra: Default::default(),
+///     forbids_unsafe: false,
+///     abbrev_len: 7,
+/// };
+/// let main = [commit(0), commit(1)];
+/// let feature = [commit(0), commit(1), commit(2)];
+///
+/// let histories = [("main", &main[..]), ("feature", &feature[..])];
+/// let svg = plot_comparison_svg(&histories, None, &PlotOptions::default())?;
+/// assert!(svg.contains("main unsafe"));
+/// assert!(svg.contains("feature unsafe"));
+/// # Ok::<(), unsafe_track::plot::PlotError>(())
+/// ```
+pub fn plot_comparison_svg(
+    histories: &[(&str, &[CommitResult])],
+    metadata: Option<&ChartMetadata>,
+    options: &PlotOptions,
+) -> Result<String, PlotError> {
+    let mut buf = String::new();
+    let root = SVGBackend::with_string(&mut buf, image_size(options)).into_drawing_area();
+    plot_comparison(histories, metadata, options, &root)?;
+    drop(root);
+    if let Some(metadata) = metadata {
+        embed_provenance(&mut buf, metadata, options);
+    }
+    Ok(buf)
+}
+
+/// Renders the chart comparing several labeled histories into the drawing area, using all of it
+///
+/// The caption from `metadata` is drawn like in [`plot_results`].
+/// If there are no results in any of them, a placeholder with a message is drawn instead.
+#[tracing::instrument(skip(histories, metadata, root), fields(history_count = histories.len()))]
+pub fn plot_comparison<DB>(
+    histories: &[(&str, &[CommitResult])],
+    metadata: Option<&ChartMetadata>,
+    options: &PlotOptions,
+    root: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    if options.style.is_sparkline() {
+        let results = histories.first().map_or(&[][..], |&(_, results)| results);
+        return plot_sparkline(results, options, root);
+    }
+    if histories.iter().all(|(_, results)| results.is_empty()) {
+        return plot_placeholder("No matching Rust files", options, root);
+    }
+
+    let area = split_caption(metadata, options, root)?;
+    plot_histories(histories, options, &area)
+}
+
+/// The size of the SVG images, in pixels
+fn image_size(options: &PlotOptions) -> (u32, u32) {
+    let (width, height) = if options.style.is_sparkline() {
+        (300, 60)
+    } else {
+        let subplots = if options.subplots {
+            options.extra_y_coords.len() as u32
+        } else {
+            0
+        };
+        (800, 400 + subplots * SUBPLOT_HEIGHT)
+    };
+    (
+        options.width.unwrap_or(width),
+        options.height.unwrap_or(height),
+    )
+}
+
+/// Draws the caption of `metadata` at the top of `root`, returning the area left for the chart
+fn split_caption<DB>(
+    metadata: Option<&ChartMetadata>,
+    options: &PlotOptions,
     root: &DrawingArea<DB, Shift>,
-) where
+) -> Result<DrawingArea<DB, Shift>, PlotError>
+where
     DB: DrawingBackend,
 {
-    match x_coord {
-        XCoord::Date => plot_results_impl(results, XIsDate, y_coord, root),
-        XCoord::Index => plot_results_impl(results, XIsIndex, y_coord, root),
+    let Some(metadata) = metadata else {
+        return Ok(root.clone());
+    };
+    options.fill_background(root)?;
+    let (caption_area, chart_area) = root.split_vertically(CAPTION_HEIGHT);
+    draw_caption(metadata, &caption_area)?;
+    Ok(chart_area)
+}
+
+fn plot_histories<DB>(
+    histories: &[(&str, &[CommitResult])],
+    options: &PlotOptions,
+    root: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    let plot = |options: &PlotOptions, x_axis: bool, area: &DrawingArea<DB, Shift>| match options
+        .x_coord
+    {
+        XCoord::Date => {
+            plot_results_impl(histories, XIsDate(options.timezone), options, x_axis, area)
+        }
+        XCoord::Index => plot_results_impl(histories, XIsIndex, options, x_axis, area),
+    };
+    if !options.subplots || options.extra_y_coords.is_empty() {
+        return plot(options, true, root);
+    }
+
+    let y_coords = std::iter::once(options.y_coord)
+        .chain(options.extra_y_coords.iter().copied())
+        .collect::<Vec<_>>();
+    let areas = root.split_evenly((y_coords.len(), 1));
+    for (i, (&y_coord, area)) in y_coords.iter().zip(&areas).enumerate() {
+        let subplot_options = PlotOptions {
+            y_coord,
+            extra_y_coords: Vec::new(),
+            threshold: options.threshold.filter(|_| i == 0),
+            ..options.clone()
+        };
+        // the X axis is the same for all of them, so only the bottom one has it
+        plot(&subplot_options, i == y_coords.len() - 1, area)?;
+    }
+    Ok(())
+}
+
+/// Prefixes the label of a series with the name of its history, if there is one
+fn series_label(name: &str, label: &str) -> String {
+    if name.is_empty() {
+        label.to_string()
+    } else {
+        format!("{} {}", name, label)
     }
 }
 
+/// Draws the first metric of `results` as a line without the axes, which is flat without any
+/// results
+fn plot_sparkline<DB>(
+    results: &[CommitResult],
+    options: &PlotOptions,
+    root: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    let y_coord = options.y_coord;
+    let values = options
+        .mode
+        .values(results, y_coord)
+        .into_iter()
+        // the unsafe part of the others is always zero
+        .map(|(unsafe_, safe)| if y_coord.is_split() { unsafe_ } else { safe })
+        .collect::<Vec<_>>();
+    // a single value still makes a line across
+    let points = match values.as_slice() {
+        [] => vec![(0, 0), (1, 0)],
+        &[value] => vec![(0, value), (1, value)],
+        values => values
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, v)| (i as i64, v))
+            .collect(),
+    };
+    let max_x = points.len() as i64 - 1;
+
+    let min_y = values.iter().copied().min().unwrap_or(0);
+    let max_y = values.iter().copied().max().unwrap_or(0);
+    // the line stays off the edges, and a flat one is in the middle
+    let padding = ((max_y - min_y) / 10).max(1);
+    let (min_y, max_y) = (min_y - padding, max_y + padding);
+
+    let rising = values.last() > values.first();
+    let color = if rising {
+        options.palette.unsafe_color()
+    } else {
+        options.palette.safe_color()
+    };
+    let style = color.stroke_width(2);
+
+    options.fill_background(root)?;
+    let mut chart = ChartBuilder::on(root)
+        .margin(2)
+        .build_cartesian_2d(0..max_x, min_y..max_y)?;
+    let points = if options.step {
+        step_points(points)
+    } else {
+        points
+    };
+    if options.style == PlotStyle::SparklineArea {
+        chart.draw_series(
+            AreaSeries::new(points.iter().copied(), min_y, color.mix(0.2)).border_style(style),
+        )?;
+    } else {
+        chart.draw_series(LineSeries::new(points, style))?;
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// The corners of a line held at each value until the next point, where it goes straight up or
+/// down to the next value
+fn step_points<X: Copy, Y: Copy>(points: impl IntoIterator<Item = (X, Y)>) -> Vec<(X, Y)> {
+    let mut steps = Vec::new();
+    for (x, y) in points {
+        if let Some(&(_, previous)) = steps.last() {
+            steps.push((x, previous));
+        }
+        steps.push((x, y));
+    }
+    steps
+}
+
+fn plot_placeholder<DB>(
+    message: &str,
+    options: &PlotOptions,
+    root: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+{
+    options.fill_background(root)?;
+
+    let (width, height) = root.dim_in_pixel();
+    let style = ("sans-serif", 20)
+        .into_text_style(root)
+        .pos(Pos::new(HPos::Center, VPos::Center));
+    root.draw_text(message, &style, (width as i32 / 2, height as i32 / 2))?;
+
+    root.present()?;
+
+    Ok(())
+}
+
 // TODO: maybe plot by commit number?
 fn plot_results_impl<DB, X>(
-    results: &[CommitResult],
+    histories: &[(&str, &[CommitResult])],
     x_coord: X,
-    y_coord: YCoord,
+    options: &PlotOptions,
+    x_axis: bool,
     root: &DrawingArea<DB, Shift>,
-) where
+) -> Result<(), PlotError>
+where
     DB: DrawingBackend,
     X: XCoordTrait,
 {
-    drop(x_coord);
+    let y_coord = options.y_coord;
+    // a single history keeps the colors telling unsafe from safe, the compared ones get a color each
+    let compared = histories.len() > 1;
 
-    let x_values = results.iter().map(|c| X::get_x_coord(c));
-    let min_x = x_values.clone().min().unwrap();
-    let max_x = x_values.max().unwrap();
+    let all_results = || histories.iter().flat_map(|(_, results)| results.iter());
+
+    let (min_x, max_x) = match &options.x_range {
+        Some(range) => fixed_range(
+            &(x_coord.fixed_x(range.start)..x_coord.fixed_x(range.end)),
+            X::widen_empty,
+        ),
+        None => {
+            let x_values = all_results().map(|c| x_coord.get_x_coord(c));
+            let min_x = x_values.clone().min().expect("BUG: no results to plot");
+            let max_x = x_values.max().expect("BUG: no results to plot");
+            X::widen_empty(min_x, max_x)
+        }
+    };
 
     let x_ranged = X::make_ranged(min_x, max_x);
 
-    let max_count = results
+    let y_coords = std::iter::once(y_coord)
+        .chain(options.extra_y_coords.iter().copied())
+        .collect::<Vec<_>>();
+    // the metrics can differ by orders of magnitude, so each line gets scaled to its own maximum
+    let normalized = y_coords.len() > 1;
+
+    // the history shared with the ones before is drawn by them, each of the others is drawn from
+    // the commit it forks off at
+    let mut drawn = HashSet::new();
+    let starts = histories
         .iter()
-        .map(|c| {
-            let c = y_coord.get_counts(c);
-            std::cmp::max(c.unsafe_, c.safe)
+        .map(|(_, results)| {
+            let shared = results
+                .iter()
+                .take_while(|c| drawn.contains(&c.oid))
+                .count();
+            drawn.extend(results.iter().map(|c| c.oid));
+            shared.saturating_sub(1)
         })
+        .collect::<Vec<_>>();
+
+    let mut lines = Vec::new();
+    for (&(name, results), &start) in histories.iter().zip(&starts) {
+        let xs = results
+            .iter()
+            .map(|c| x_coord.get_x_coord(c))
+            .collect::<Vec<_>>();
+        for &metric in &y_coords {
+            let points = options.mode.values(results, metric);
+            let series = if metric.is_split() {
+                let (unsafe_color, safe_color, total_color) = if normalized {
+                    let color = options
+                        .palette
+                        .color_for(&series_label(name, &metric.label()));
+                    (color.to_rgba(), color.mix(0.5), color.mix(0.25))
+                } else if compared {
+                    let color = options.palette.color_for(name);
+                    (color.to_rgba(), color.mix(0.5), color.mix(0.25))
+                } else {
+                    (
+                        options.palette.unsafe_color().to_rgba(),
+                        options.palette.safe_color().to_rgba(),
+                        // the context of the others, not a series of its own color
+                        BLACK.mix(0.35),
+                    )
+                };
+                let label = |safety| {
+                    if normalized {
+                        series_label(name, &format!("{} {}", metric.label(), safety))
+                    } else {
+                        series_label(name, safety)
+                    }
+                };
+                let mut series = vec![(
+                    label("unsafe"),
+                    unsafe_color,
+                    points
+                        .iter()
+                        .map(|&(unsafe_, _)| unsafe_)
+                        .collect::<Vec<_>>(),
+                )];
+                if !options.unsafe_only {
+                    series.push((
+                        label("safe"),
+                        safe_color,
+                        points.iter().map(|&(_, safe)| safe).collect(),
+                    ));
+                }
+                if options.show_total {
+                    series.push((
+                        label("total"),
+                        total_color,
+                        points
+                            .iter()
+                            .map(|&(unsafe_, safe)| unsafe_ + safe)
+                            .collect(),
+                    ));
+                }
+                series
+            } else {
+                // the unsafe part is always zero
+                let label = if normalized {
+                    series_label(name, &metric.label())
+                } else if compared {
+                    name.to_string()
+                } else {
+                    "total".to_string()
+                };
+                vec![(
+                    label.clone(),
+                    options.palette.color_for(&label).to_rgba(),
+                    points.iter().map(|&(_, total)| total).collect(),
+                )]
+            };
+
+            for (label, color, values) in series {
+                let (label, values) = if normalized {
+                    let max = values.iter().map(|v| v.abs()).max().unwrap_or(0);
+                    (
+                        format!("{} (100% = {})", label, max),
+                        values
+                            .iter()
+                            .map(|v| v * NORMALIZED_MAX / max.max(1))
+                            .collect(),
+                    )
+                } else {
+                    (label, values)
+                };
+                if let Some(window) = options.smooth {
+                    // the results are sorted by date, which is also the order of the commit indices
+                    let smoothed = moving_average(&values, window as usize);
+                    lines.push((
+                        format!("{} (average of {})", label, window),
+                        color.mix(0.5).stroke_width(3),
+                        xs[start..].to_vec(),
+                        smoothed[start..].to_vec(),
+                        // the average isn't a commit
+                        false,
+                        false,
+                    ));
+                }
+                if options.smooth.is_none() || !options.smooth_only {
+                    lines.push((
+                        label,
+                        color.stroke_width(1),
+                        xs[start..].to_vec(),
+                        values[start..].to_vec(),
+                        options.markers,
+                        metric.is_points(),
+                    ));
+                }
+            }
+        }
+    }
+    // the smoothed lines stay within the raw ones
+    let all_values = || {
+        lines
+            .iter()
+            .flat_map(|(_, _, _, values, _, _)| values.iter().copied())
+    };
+
+    let (min_count, max_count) = match &options.y_range {
+        Some(range) => {
+            let scale = if normalized { NORMALIZED_MAX / 100 } else { 1 };
+            fixed_range(&(range.start * scale..range.end * scale), |min, max| {
+                (min, max.max(min + 1))
+            })
+        }
+        None => {
+            // deltas can go below zero
+            let min_count = all_values().min().unwrap_or(0).min(0);
+            // keep the range non-empty even if there is nothing counted
+            let max_count = all_values().max().unwrap_or(0).max(min_count + 1);
+            (min_count, max_count)
+        }
+    };
+
+    let draw_ratio = options.ratio_axis && options.mode == PlotMode::Absolute && y_coord.is_split();
+    let max_failures = all_results()
+        .map(|c| c.failed_files_count)
         .max()
-        .unwrap();
+        .unwrap_or(0);
+    // the undercounted commits are worth pointing out, the rest is clutter
+    let draw_failures = options.show_failures && max_failures > 0;
+    // both share the secondary axis, the failures are drawn as a percentage next to the ratio
+    let secondary_range = if draw_ratio || !draw_failures {
+        0.0..100.0
+    } else {
+        0.0..max_failures as f64
+    };
 
-    root.fill(&WHITE).unwrap();
-    let mut chart = ChartBuilder::on(&root)
+    options.fill_background(root)?;
+    let mut builder = ChartBuilder::on(root);
+    builder
         // .caption("y=x^2", ("sans-serif", 50).into_font())
         // .margin(5)
-        .x_label_area_size(60)
-        .y_label_area_size(60)
-        .build_cartesian_2d(x_ranged, 0..max_count)
-        .unwrap();
+        .x_label_area_size(if x_axis { 60 } else { 10 })
+        .y_label_area_size(60);
+    if draw_ratio || draw_failures {
+        builder.right_y_label_area_size(60);
+    }
+    let mut chart = builder
+        .build_cartesian_2d(x_ranged, min_count..max_count)?
+        // only gets an axis when the ratio or the failures are drawn
+        .set_secondary_coord(X::make_ranged(min_x, max_x), secondary_range);
 
-    chart
-        .configure_mesh()
-        .x_desc(X::AXIS_DESCRIPTION)
-        .y_desc(match y_coord {
-            YCoord::Functions => "Function count",
-            YCoord::Expressions => "Expression count",
+    let mut mesh = chart.configure_mesh();
+    if x_axis {
+        mesh.x_desc(x_coord.axis_description());
+    }
+    // without the labels, the grid still lines up with the chart having them
+    let format_x = |x: &X::Value| {
+        if x_axis {
+            x_coord.format_x(x)
+        } else {
+            String::new()
+        }
+    };
+    mesh.x_label_formatter(&format_x)
+        .y_desc(if normalized {
+            match options.mode {
+                PlotMode::Absolute => "% of the largest value of each line".to_string(),
+                PlotMode::Delta => "Change, % of the largest change of each line".to_string(),
+            }
+        } else {
+            let description = match y_coord {
+                YCoord::Functions => "Function count",
+                YCoord::Expressions => "Expression count",
+                YCoord::LinesOfCode => "Lines of code",
+                YCoord::FfiFunctions => "FFI function count",
+                YCoord::UnsafeBlocks => "Unsafe block count",
+                YCoord::UnsafeFunctions => "Unsafe function count",
+                YCoord::UnsafeImpls => "Unsafe impl count",
+                YCoord::Introduced => "New function count",
+                YCoord::Counters(kinds) => &format!("Count of {}", kinds.label()),
+            };
+            match options.mode {
+                PlotMode::Absolute => description.to_string(),
+                PlotMode::Delta => format!("{} change", description),
+            }
+        })
+        .y_label_formatter(&|&y| {
+            if normalized {
+                format!("{}%", y as f64 * 100.0 / NORMALIZED_MAX as f64)
+            } else {
+                y.to_string()
+            }
         })
         .axis_desc_style(("sans-serif", 15))
-        .draw()
-        .unwrap();
+        .draw()?;
+    if draw_ratio {
+        chart
+            .configure_secondary_axes()
+            .y_desc(if draw_failures {
+                "Unsafe share and failed files, %"
+            } else {
+                "Unsafe share, %"
+            })
+            .axis_desc_style(("sans-serif", 15))
+            .draw()?;
+    } else if draw_failures {
+        chart
+            .configure_secondary_axes()
+            .y_desc("Failed files")
+            // whole files only
+            .y_labels(max_failures.min(10) + 1)
+            .y_label_formatter(&|v| format!("{:.0}", v))
+            .axis_desc_style(("sans-serif", 15))
+            .draw()?;
+    }
 
-    let unsafe_series = LineSeries::new(
-        results
-            .iter()
-            .map(|c| (X::get_x_coord(c), y_coord.get_counts(&c).unsafe_)),
-        &RED,
-    );
-    let safe_series = LineSeries::new(
-        results
-            .iter()
-            .map(|c| (X::get_x_coord(c), y_coord.get_counts(&c).safe)),
-        &GREEN,
-    );
+    if options.mode == PlotMode::Delta {
+        chart.draw_series(LineSeries::new([(min_x, 0), (max_x, 0)], &BLACK))?;
+    }
 
-    chart
-        .draw_series(unsafe_series)
-        .unwrap()
-        .label("unsafe")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
-    chart
-        .draw_series(safe_series)
-        .unwrap()
-        .label("safe")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
-
-    chart
-        .configure_series_labels()
-        .position(SeriesLabelPosition::MiddleLeft)
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()
-        .unwrap();
+    // neutral, under the series
+    let threshold_style = BLACK.mix(0.5).stroke_width(1);
+    let threshold_label = ("sans-serif", 12)
+        .into_font()
+        .color(&BLACK.mix(0.7))
+        .pos(Pos::new(HPos::Left, VPos::Bottom));
+    if let Some(threshold) = options.threshold.filter(|_| !normalized) {
+        let y = threshold.clamp(min_count, max_count);
+        chart.draw_series(DashedLineSeries::new(
+            [(min_x, y), (max_x, y)],
+            8,
+            4,
+            threshold_style,
+        ))?;
+        chart.draw_series(std::iter::once(Text::new(
+            format!("threshold {}", threshold),
+            (min_x, y),
+            threshold_label.clone(),
+        )))?;
+    }
+    if let Some(threshold) = options.ratio_threshold.filter(|_| draw_ratio) {
+        let y = threshold.clamp(0.0, 100.0);
+        chart.draw_secondary_series(DashedLineSeries::new(
+            [(min_x, y), (max_x, y)],
+            8,
+            4,
+            threshold_style,
+        ))?;
+        chart.draw_secondary_series(std::iter::once(Text::new(
+            format!("threshold {}%", threshold),
+            (min_x, y),
+            threshold_label,
+        )))?;
+    }
+
+    // the legend lists the lines in the order they are drawn, put the highest ones first
+    lines.sort_by_key(|(_, _, _, values, _, _)| std::cmp::Reverse(values.last().copied()));
+
+    let hidden_labels = lines.len().saturating_sub(MAX_LEGEND_ENTRIES);
+    for (i, (label, style, xs, values, marked, points)) in lines.into_iter().enumerate() {
+        if points {
+            let series = chart.draw_series(
+                xs.iter()
+                    .zip(&values)
+                    .map(|(&x, &y)| Circle::new((x, y), 3, style.filled())),
+            )?;
+            if i < MAX_LEGEND_ENTRIES {
+                series
+                    .label(label)
+                    .legend(move |(x, y)| Circle::new((x + 10, y), 3, style.filled()));
+            }
+            continue;
+        }
+        // a single commit makes no line, mark it instead
+        if let ([x], [y]) = (xs.as_slice(), values.as_slice()) {
+            chart.draw_series(std::iter::once(Circle::new((*x, *y), 3, style.filled())))?;
+        } else if marked && xs.len() <= MAX_MARKED_COMMITS {
+            chart.draw_series(
+                xs.iter()
+                    .zip(&values)
+                    .map(|(&x, &y)| Circle::new((x, y), 2, style.filled())),
+            )?;
+        }
+        let line = xs.into_iter().zip(values);
+        let series = if options.step {
+            chart.draw_series(LineSeries::new(step_points(line), style))?
+        } else {
+            chart.draw_series(LineSeries::new(line, style))?
+        };
+        if i < MAX_LEGEND_ENTRIES {
+            series
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+    }
+    for (&(name, results), &start) in histories.iter().zip(&starts) {
+        let results = &results[start..];
+        if results.is_empty() {
+            continue;
+        }
+        if draw_ratio {
+            let color = if compared {
+                options.palette.color_for(name)
+            } else {
+                options.palette.color_for("unsafe share")
+            };
+            let style = color.stroke_width(2);
+            let ratios = results.iter().map(|c| {
+                let counts = y_coord.get_counts(c);
+                let total = counts.unsafe_ + counts.safe;
+                // nothing counted, nothing unsafe
+                let ratio = if total == 0 {
+                    0.0
+                } else {
+                    counts.unsafe_ as f64 * 100.0 / total as f64
+                };
+                (x_coord.get_x_coord(c), ratio)
+            });
+            chart
+                .draw_secondary_series(DashedLineSeries::new(ratios, 6, 4, style))?
+                .label(if normalized {
+                    series_label(name, &format!("{} unsafe share", y_coord.label()))
+                } else {
+                    series_label(name, "unsafe share")
+                })
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+        if draw_failures {
+            let color = if compared {
+                options.palette.color_for(name)
+            } else {
+                options.palette.failures_color()
+            };
+            let style = color.stroke_width(1);
+            let failures = results.iter().map(|c| {
+                let failed = c.failed_files_count as f64;
+                let value = if !draw_ratio {
+                    failed
+                } else if c.matched_files_count == 0 {
+                    0.0
+                } else {
+                    failed * 100.0 / c.matched_files_count as f64
+                };
+                (x_coord.get_x_coord(c), value)
+            });
+            chart
+                .draw_secondary_series(DashedLineSeries::new(failures, 3, 3, style))?
+                .label(series_label(
+                    name,
+                    if draw_ratio {
+                        "failed files, %"
+                    } else {
+                        "failed files"
+                    },
+                ))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+    }
+    if hidden_labels > 0 {
+        chart
+            .draw_series(std::iter::empty::<PathElement<_>>())?
+            .label(format!("(+{} more)", hidden_labels))
+            .legend(|(x, y)| EmptyElement::at((x, y)));
+    }
+
+    if !options.hide_legend {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::MiddleLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+
+    root.present()?;
 
-    root.present().unwrap();
+    Ok(())
 }
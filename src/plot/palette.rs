@@ -0,0 +1,95 @@
+//! Colors of the chart series, both the safe/unsafe ones and the ones without an inherent meaning
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use plotters::style::{RGBColor, GREEN, RED};
+use serde::{Deserialize, Serialize};
+
+/// Mid-luminance colors, readable on both light and dark backgrounds
+pub const PALETTE: [RGBColor; 12] = [
+    RGBColor(0x4e, 0x79, 0xa7), // blue
+    RGBColor(0xf2, 0x8e, 0x2b), // orange
+    RGBColor(0xe1, 0x57, 0x59), // red
+    RGBColor(0x76, 0xb7, 0xb2), // teal
+    RGBColor(0x59, 0xa1, 0x4f), // green
+    RGBColor(0xed, 0xc9, 0x48), // yellow
+    RGBColor(0xb0, 0x7a, 0xa1), // purple
+    RGBColor(0xff, 0x9d, 0xa7), // pink
+    RGBColor(0x9c, 0x75, 0x5f), // brown
+    RGBColor(0xba, 0xb0, 0xac), // grey
+    RGBColor(0x17, 0xbe, 0xcf), // cyan
+    RGBColor(0xbc, 0xbd, 0x22), // olive
+];
+
+/// The Okabe-Ito colors, distinguishable with all the common color vision deficiencies
+pub const COLOR_UNIVERSAL_PALETTE: [RGBColor; 7] = [
+    RGBColor(0xe6, 0x9f, 0x00), // orange
+    RGBColor(0x56, 0xb4, 0xe9), // sky blue
+    RGBColor(0x00, 0x9e, 0x73), // bluish green
+    RGBColor(0xf0, 0xe4, 0x42), // yellow
+    RGBColor(0x00, 0x72, 0xb2), // blue
+    RGBColor(0xd5, 0x5e, 0x00), // vermillion
+    RGBColor(0xcc, 0x79, 0xa7), // reddish purple
+];
+
+/// The set of colors the chart is drawn with
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Palette {
+    /// Red for unsafe and green for safe
+    #[default]
+    Default,
+    /// Color universal design: vermillion for unsafe and blue for safe, safe for color-blind readers
+    Cud,
+}
+
+impl Palette {
+    pub fn unsafe_color(self) -> RGBColor {
+        match self {
+            Palette::Default => RED,
+            Palette::Cud => COLOR_UNIVERSAL_PALETTE[5],
+        }
+    }
+
+    pub fn safe_color(self) -> RGBColor {
+        match self {
+            Palette::Default => GREEN,
+            Palette::Cud => COLOR_UNIVERSAL_PALETTE[4],
+        }
+    }
+
+    /// The color of the files that could not be analysed, some orange
+    pub fn failures_color(self) -> RGBColor {
+        match self {
+            Palette::Default => PALETTE[1],
+            Palette::Cud => COLOR_UNIVERSAL_PALETTE[0],
+        }
+    }
+
+    /// Picks the color of a series without an inherent meaning, see [`color_for`]
+    pub fn color_for(self, label: &str) -> RGBColor {
+        match self {
+            Palette::Default => color_for(label),
+            Palette::Cud => pick(&COLOR_UNIVERSAL_PALETTE, label),
+        }
+    }
+}
+
+/// Picks the color of a series by its label, so it stays the same between renders
+///
+/// ```
+/// use unsafe_track::plot::palette::color_for;
+///
+/// assert_eq!(color_for("tokio"), color_for("tokio"));
+/// ```
+pub fn color_for(label: &str) -> RGBColor {
+    pick(&PALETTE, label)
+}
+
+fn pick(colors: &[RGBColor], label: &str) -> RGBColor {
+    // FNV-1a, unlike `DefaultHasher` it's guaranteed to stay the same across Rust versions
+    let hash = label.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    colors[(hash % colors.len() as u64) as usize]
+}
@@ -1,7 +1,11 @@
-use crate::analysis::AnalysisCache;
-use crate::plot::{XCoord, YCoord};
-use crate::{analysis, plot};
-use axum::extract::State;
+use crate::config::{Config, PresetOptions};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderName, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Json;
 use axum::{
     extract::{Path, Query},
     routing::get,
@@ -9,82 +13,1353 @@ use axum::{
 };
 use axum_extra::TypedHeader;
 use axum_tracing_opentelemetry::middleware::{OtelAxumLayer, OtelInResponseLayer};
+use connections::{ConnectionLimits, ConnectionStats, RequestLimits};
+use gix_hash::ObjectId;
 use headers::{CacheControl, ContentType};
+use hmac::{Hmac, Mac};
+use moka::sync::Cache;
+use preload::PreloadStatus;
 use regex::Regex;
-use serde::Deserialize;
+use repos::AnalysedRepos;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tower_http::catch_panic::CatchPanicLayer;
-use tracing::{info, info_span, Span};
+use tower_http::compression::CompressionLayer;
+use tower_http::timeout::TimeoutLayer;
+use tracing::{info, info_span, warn, Span};
+use unsafe_track::analysis::{
+    AnalysisCache, AnalysisError, AnalysisLimits, BundleStore, CacheStats, Mirror, PathFilter,
+    RepoAnalysis, Timings,
+};
+use unsafe_track::github::{self, GithubRepo};
+use unsafe_track::plot::palette::Palette;
+use unsafe_track::plot::{
+    ChartMetadata, CountKind, DateTimezone, PlotMode, PlotOptions, PlotStyle, XCoord, YCoord,
+    IMAGE_SIZE_RANGE, MAX_SMOOTHING_WINDOW,
+};
+use unsafe_track::{analysis, plot};
+
+mod connections;
+mod csv;
+mod index;
+mod metrics;
+mod plan;
+mod preload;
+mod repos;
+#[cfg(unix)]
+mod unix_socket;
+mod view;
+
+/// Approximate memory taken by the cached file results, in bytes
+const ANALYSIS_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+/// Disk space taken by the cached indexed packs, in bytes
+const PACK_CACHE_SIZE: u64 = 1024 * 1024 * 1024;
+// long enough for a burst of requests trying out filters, the refs are checked before each anyway
+const PACK_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// How often the health of the file results cache is logged
+const CACHE_STATS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const RESULT_CACHE_SIZE: u64 = 1_000;
+const FAILURE_CACHE_SIZE: u64 = 10_000;
+// long enough to spare the embedded badges of a missing repository, short enough to notice it's back
+const FAILURE_CACHE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Everything the analysis of a repository depends on
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct AnalysisKey {
+    /// `owner/repo`, lowercased as GitHub names are case-insensitive
+    repo: String,
+    ref_name: String,
+    path_filter: String,
+    exclude: Option<String>,
+    use_ignore_file: bool,
+    skip_vendored: bool,
+    subdir: Option<String>,
+    expand_local_macros: bool,
+}
+
+/// A cached analysis, current as long as the analysed ref still points to `tip`
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct ResultKey {
+    analysis: AnalysisKey,
+    tip: ObjectId,
+}
+
+impl AnalysisKey {
+    fn new(repo: &GithubRepo, params: &GithubParams) -> Self {
+        Self {
+            repo: repo.key(),
+            // a commit is fetched by its id, the same way as a ref by its name
+            ref_name: params
+                .commit
+                .clone()
+                .or_else(|| params.ref_name.clone())
+                .unwrap_or("HEAD".to_string()),
+            path_filter: params
+                .path_filter
+                .clone()
+                .unwrap_or_else(|| default_path_filter().to_string()),
+            exclude: params.exclude.clone(),
+            use_ignore_file: params.ignore_file.unwrap_or(true),
+            skip_vendored: params.skip_vendored,
+            // the same directory can be spelled with or without the slashes around it
+            subdir: params
+                .subdir
+                .as_deref()
+                .map(|subdir| subdir.trim_matches('/').to_string())
+                .filter(|subdir| !subdir.is_empty()),
+            expand_local_macros: params.expand_local_macros,
+        }
+    }
+
+    /// The filters of the analysis, which [`Self::check_regexes`] must have passed
+    fn path_filter(&self) -> PathFilter {
+        PathFilter {
+            include: Regex::new(&self.path_filter).unwrap(),
+            exclude: self.exclude.as_ref().map(|e| Regex::new(e).unwrap()),
+            use_ignore_file: self.use_ignore_file,
+            skip_vendored: self.skip_vendored,
+            subdir: self.subdir.clone(),
+            expand_local_macros: self.expand_local_macros,
+        }
+    }
 
-const ANALYSIS_CACHE_SIZE: u64 = 50_000;
+    /// Checks that the filters are valid regexes, which the analysis expects
+    fn check_regexes(&self) -> Result<(), regex::Error> {
+        for regex in std::iter::once(&self.path_filter).chain(&self.exclude) {
+            Regex::new(regex)?;
+        }
+        Ok(())
+    }
+}
+
+/// The filter of the requests without a `path_filter`, from [`crate::DEFAULT_FILTER_ENV`] if it's
+/// set
+fn default_path_filter() -> &'static str {
+    static DEFAULT: OnceLock<String> = OnceLock::new();
+    DEFAULT.get_or_init(|| {
+        std::env::var(crate::DEFAULT_FILTER_ENV)
+            .ok()
+            .filter(|filter| !filter.is_empty())
+            .unwrap_or_else(|| crate::DEFAULT_FILTER.to_string())
+    })
+}
+
+/// Serves the charts of GitHub repositories over HTTP
+#[derive(clap::Args, Debug)]
+#[clap(group(clap::ArgGroup::new("listen").required(true)))]
+pub struct ServerConfig {
+    /// TCP port to listen on, on all the interfaces
+    #[clap(group = "listen")]
+    port: Option<u16>,
+    /// Unix domain socket to listen on instead of a TCP port, like for a reverse proxy on the same
+    /// host. The socket file is removed on SIGINT or SIGTERM.
+    #[cfg(unix)]
+    #[clap(long, group = "listen")]
+    unix_socket: Option<PathBuf>,
+    /// Secret of the GitHub webhook invalidating the cached results on push, the webhook is disabled without it
+    #[clap(long, env = "GITHUB_WEBHOOK_SECRET")]
+    webhook_secret: Option<String>,
+    /// Wall-clock limit of a single analysis, in seconds
+    #[clap(long, default_value_t = 300)]
+    analysis_timeout: u64,
+    /// Maximum number of distinct matching files in the history of an analysed repository
+    #[clap(long, default_value_t = 200_000)]
+    max_blobs: usize,
+    /// Maximum size of the pack of an analysed repository, as sent by GitHub
+    #[clap(long, default_value_t = analysis::DEFAULT_MAX_PACK_BYTES)]
+    max_pack_bytes: u64,
+    /// Maximum number of objects in the pack of an analysed repository
+    #[clap(long, default_value_t = analysis::DEFAULT_MAX_OBJECTS)]
+    max_objects: u64,
+    /// Maximum total size of the objects of an analysed repository once decompressed
+    #[clap(long, default_value_t = analysis::DEFAULT_MAX_DECOMPRESSED_BYTES)]
+    max_decompressed_bytes: u64,
+    /// Approximate memory an analysis may take for the history and its results, not counting the
+    /// cached per-file results
+    #[clap(long, default_value_t = analysis::DEFAULT_MEMORY_BUDGET)]
+    memory_budget: u64,
+    /// Analyse the matching files in batches of about this many, which walks the history again for
+    /// every batch but takes less memory. All at once by default
+    #[clap(long)]
+    blob_batch_size: Option<NonZeroUsize>,
+    /// Deepest directory walked in the tree of a commit, the deeper ones are left out
+    #[clap(long, default_value_t = analysis::DEFAULT_MAX_TREE_DEPTH)]
+    max_tree_depth: usize,
+    /// Maximum total length of the paths in the tree of a commit, the rest of the tree is left out
+    #[clap(long, default_value_t = analysis::DEFAULT_MAX_PATH_BYTES)]
+    max_path_bytes: usize,
+    /// Number of threads analysing the files, shared by all the analyses. All the logical CPUs but
+    /// two by default, which are left to the async runtime and the fetches
+    #[clap(long)]
+    threads: Option<NonZeroUsize>,
+    /// How many times a fetch failing with a transient network error is retried
+    #[clap(long, default_value_t = 3)]
+    fetch_retries: u32,
+    /// Milliseconds to wait before the first retry of a fetch, doubled for each following one
+    #[clap(long, default_value_t = 1000)]
+    fetch_retry_delay_ms: u64,
+    /// Longest wait between two retries of a fetch, in milliseconds
+    #[clap(long, default_value_t = 60_000)]
+    max_fetch_retry_delay_ms: u64,
+    /// Directory for the downloaded packs and their indices, instead of the system temp directory
+    #[clap(long)]
+    temp_dir: Option<PathBuf>,
+    /// Prefix of a mirror of GitHub, like `https://mirror.example/github/`, to fetch `owner/repo` from
+    /// when fetching it from GitHub fails
+    #[clap(long)]
+    mirror_prefix: Option<String>,
+    /// Repository to analyse in the background on startup, like `owner/repo` or `owner/repo?ref=v1.0&subdir=rust`
+    /// with the query parameters of the chart. Can be repeated.
+    #[clap(long)]
+    preload: Vec<String>,
+    /// Approximate memory the cached per-file results may take, in bytes
+    #[clap(long, default_value_t = ANALYSIS_CACHE_SIZE)]
+    analysis_cache_bytes: u64,
+    /// Disk space the downloaded and indexed packs may take in the temporary directory, kept for a
+    /// few minutes for the analyses of the same histories with other filters. 0 disables it.
+    #[clap(long, default_value_t = PACK_CACHE_SIZE)]
+    pack_cache_bytes: u64,
+    /// Fetch the commits and the trees first, and then only the matching files, from the servers
+    /// that support leaving the blobs out
+    #[clap(long)]
+    partial_fetch: bool,
+    /// Connections served at once, the following ones wait to be accepted
+    #[clap(long, default_value_t = 1024)]
+    max_connections: usize,
+    /// Seconds a client has to send the headers of a request, and the body of a webhook delivery
+    #[clap(long, default_value_t = 30)]
+    request_timeout: u64,
+    /// Seconds a connection is kept open without a request, before its first one too
+    #[clap(long, default_value_t = 60)]
+    idle_timeout: u64,
+    /// Maximum size of the request line and the headers of a request, at least 8192
+    #[clap(long, default_value_t = 64 * 1024, value_parser = clap::value_parser!(u64).range(8192..))]
+    max_header_bytes: u64,
+    /// Maximum length of the query of a request, the filters included
+    #[clap(long, default_value_t = 8 * 1024)]
+    max_query_bytes: usize,
+    /// Maximum size of the body of a webhook delivery, the only requests that have one
+    #[clap(long, default_value_t = 25 * 1024 * 1024)]
+    max_body_bytes: usize,
+}
 
 #[derive(Clone)]
 struct AppState {
     blob_analysis_cache: AnalysisCache,
+    /// Keyed by the tip of the ref too, so a result is never served once the ref moves on
+    results: Cache<ResultKey, Arc<RepoAnalysis>>,
+    /// Where the entries of `results` come from, listed by `/repos`
+    analysed_repos: AnalysedRepos,
+    webhook_secret: Option<Arc<str>>,
+    /// The presets the `preset` query parameter picks from
+    presets: Arc<Config>,
+    limits: AnalysisLimits,
+    analysis_timeout: Duration,
+    preload_status: PreloadStatus,
+    /// Repositories that failed in a way retrying won't fix soon, by the `owner/repo` of [`AnalysisKey`]
+    failures: Cache<String, CachedFailure>,
+    connection_stats: Arc<ConnectionStats>,
+    /// How many analyses of each repository went over [`AnalysisLimits::memory_budget`], by the
+    /// `owner/repo` of [`AnalysisKey`]
+    over_memory_budget: Arc<Mutex<BTreeMap<String, u64>>>,
+    /// What the `owner/repo` of a request is appended to for fetching it, [`github::URL_PREFIX`]
+    /// but in the tests
+    url_prefix: Arc<str>,
 }
 
-pub async fn start(port: u16) {
-    let middleware = tower::ServiceBuilder::new()
-        .layer(CatchPanicLayer::new())
-        // include trace context as header into the response
-        .layer(OtelInResponseLayer)
-        // start OpenTelemetry trace on incoming request
-        .layer(OtelAxumLayer::default());
+impl AppState {
+    fn new(
+        blob_analysis_cache: AnalysisCache,
+        limits: AnalysisLimits,
+        presets: Config,
+        webhook_secret: Option<String>,
+        analysis_timeout: Duration,
+    ) -> Self {
+        let analysed_repos = AnalysedRepos::default();
+        Self {
+            blob_analysis_cache,
+            results: Cache::builder()
+                .max_capacity(RESULT_CACHE_SIZE)
+                .eviction_listener({
+                    let analysed_repos = analysed_repos.clone();
+                    move |key, _, cause| analysed_repos.on_removal(&key, cause)
+                })
+                .build(),
+            analysed_repos,
+            webhook_secret: webhook_secret.map(Into::into),
+            presets: Arc::new(presets),
+            limits,
+            analysis_timeout,
+            preload_status: PreloadStatus::default(),
+            failures: Cache::builder()
+                .max_capacity(FAILURE_CACHE_SIZE)
+                .time_to_live(FAILURE_CACHE_TTL)
+                .build(),
+            connection_stats: Arc::default(),
+            over_memory_budget: Arc::default(),
+            url_prefix: github::URL_PREFIX.into(),
+        }
+    }
 
-    // create the axum server
-    let app = Router::new()
-        .route("/github/:owner/:repo", get(github))
-        .with_state(AppState {
-            blob_analysis_cache: AnalysisCache::new(ANALYSIS_CACHE_SIZE),
+    /// The URL `repo` is fetched from
+    fn repo_url(&self, repo: &GithubRepo) -> String {
+        format!("{}{}", self.url_prefix, repo)
+    }
+}
+
+#[derive(Clone)]
+struct CachedFailure {
+    status: StatusCode,
+    message: String,
+    at: Instant,
+}
+
+pub async fn start(config: ServerConfig, presets: Config) {
+    let ServerConfig {
+        port,
+        #[cfg(unix)]
+        unix_socket,
+        webhook_secret,
+        analysis_timeout,
+        max_blobs,
+        max_pack_bytes,
+        max_objects,
+        max_decompressed_bytes,
+        memory_budget,
+        blob_batch_size,
+        max_tree_depth,
+        max_path_bytes,
+        threads,
+        fetch_retries,
+        fetch_retry_delay_ms,
+        max_fetch_retry_delay_ms,
+        temp_dir,
+        mirror_prefix,
+        preload,
+        analysis_cache_bytes,
+        pack_cache_bytes,
+        partial_fetch,
+        max_connections,
+        request_timeout,
+        idle_timeout,
+        max_header_bytes,
+        max_query_bytes,
+        max_body_bytes,
+    } = config;
+
+    // rather than failing every request without a filter
+    if let Err(e) = Regex::new(default_path_filter()) {
+        panic!("Invalid {}: {}", crate::DEFAULT_FILTER_ENV, e);
+    }
+
+    // keeps the parsing off tokio's threads and bounds how many cores it takes
+    let threads = threads.unwrap_or_else(default_threads);
+    info!("Analysing the files on {} threads", threads);
+    let thread_pool = AnalysisLimits::build_thread_pool(Some(threads))
+        .expect("Failed to build the analysis thread pool");
+
+    if webhook_secret.is_none() {
+        info!("No webhook secret configured, the GitHub webhook is disabled");
+    }
+
+    let mut blob_analysis_cache = AnalysisCache::new(analysis_cache_bytes);
+    if pack_cache_bytes > 0 {
+        blob_analysis_cache = blob_analysis_cache.with_pack_cache(pack_cache_bytes, PACK_CACHE_TTL);
+    }
+    // outlives the cached packs, the same pack downloaded again once they expired isn't indexed again
+    let bundle_store = (pack_cache_bytes > 0)
+        .then(|| {
+            let dir = temp_dir
+                .clone()
+                .unwrap_or_else(std::env::temp_dir)
+                .join("unsafe-track-index");
+            BundleStore::new(&dir)
+                .map(|store| store.with_max_bytes(pack_cache_bytes))
+                .map_err(|e| warn!("Not keeping the indexed packs in {}: {}", dir.display(), e))
+                .ok()
         })
-        .layer(middleware);
+        .flatten();
+    let limits = AnalysisLimits {
+        max_blobs: Some(max_blobs),
+        thread_pool: Some(Arc::new(thread_pool)),
+        fetch_retries,
+        fetch_retry_delay: Some(Duration::from_millis(fetch_retry_delay_ms)),
+        max_fetch_retry_delay: Some(Duration::from_millis(max_fetch_retry_delay_ms)),
+        temp_dir,
+        max_pack_bytes: Some(max_pack_bytes),
+        max_objects: Some(max_objects),
+        max_decompressed_bytes: Some(max_decompressed_bytes),
+        memory_budget: Some(memory_budget),
+        blob_batch_size,
+        max_tree_depth: Some(max_tree_depth),
+        max_path_bytes: Some(max_path_bytes),
+        mirror: mirror_prefix.map(|prefix| Mirror::Prefix {
+            from: github::URL_PREFIX.to_string(),
+            // the repository path is appended as is
+            to: if prefix.ends_with('/') {
+                prefix
+            } else {
+                format!("{}/", prefix)
+            },
+        }),
+        // the charts show every commit
+        sampling: None,
+        history_limit: None,
+        partial_fetch,
+        bundle_store,
+    };
+    let state = AppState::new(
+        blob_analysis_cache,
+        limits,
+        presets,
+        webhook_secret,
+        Duration::from_secs(analysis_timeout),
+    );
+    let request_timeout = Duration::from_secs(request_timeout);
+    let connection_limits = ConnectionLimits {
+        max_connections,
+        header_timeout: request_timeout,
+        idle_timeout: Duration::from_secs(idle_timeout),
+        max_header_bytes: max_header_bytes as usize,
+    };
+
+    let app = router(
+        state.clone(),
+        max_query_bytes,
+        max_body_bytes,
+        request_timeout,
+    );
+
+    #[cfg(unix)]
+    if let Some(path) = unix_socket {
+        let socket = unix_socket::UnixSocket::bind(path).unwrap();
+        info!("Listening on {}", socket.path().display());
+        tokio::spawn(log_cache_stats(state.blob_analysis_cache.clone()));
+        let connection_stats = state.connection_stats.clone();
+        preload::spawn(state, preload);
+        socket.serve(app, connection_limits, connection_stats).await;
+        return;
+    }
 
+    let port = port.expect("BUG: clap requires the port without a socket");
     let listener =
         tokio::net::TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))
             .await
             .unwrap();
 
     info!("Listening on port {}", port);
-    axum::serve(listener, app).await.unwrap();
+    // runs in the background, the connections are accepted right away
+    tokio::spawn(log_cache_stats(state.blob_analysis_cache.clone()));
+    let connection_stats = state.connection_stats.clone();
+    preload::spawn(state, preload);
+    connections::serve(
+        &listener,
+        app,
+        connection_limits,
+        connection_stats,
+        std::future::pending(),
+    )
+    .await;
+}
+
+/// The routes of the server over `state`, with the limits on the requests
+fn router(
+    state: AppState,
+    max_query_bytes: usize,
+    max_body_bytes: usize,
+    request_timeout: Duration,
+) -> Router {
+    let middleware = tower::ServiceBuilder::new()
+        .layer(CatchPanicLayer::new())
+        // include trace context as header into the response
+        .layer(OtelInResponseLayer)
+        // start OpenTelemetry trace on incoming request
+        .layer(OtelAxumLayer::default());
+
+    // the charts of long histories are mostly path data, which compresses well
+    let compressed = Router::new()
+        .route("/github/:owner/:repo", get(github))
+        .route("/github/:owner/:repo/sparkline", get(sparkline))
+        .route("/github/:owner/:repo/csv", get(csv::csv))
+        .route("/github/:owner/:repo/plan", get(plan::plan))
+        .route("/stats", get(stats))
+        .route("/admin/cache-stats", get(stats))
+        .route("/repos", get(repos::list))
+        .route("/admin/preload-status", get(preload::status))
+        .layer(CompressionLayer::new().gzip(true).deflate(true));
+
+    Router::new()
+        .route("/", get(index::index))
+        .route("/github/:owner/:repo/view", get(view::view))
+        // left uncompressed for the scrapers
+        .route("/github/:owner/:repo/metrics", get(metrics::metrics))
+        .route(
+            "/webhook/github",
+            post(github_webhook).layer((
+                DefaultBodyLimit::max(max_body_bytes),
+                TimeoutLayer::new(request_timeout),
+            )),
+        )
+        .merge(compressed)
+        .with_state(state.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            RequestLimits {
+                max_query_bytes,
+                stats: state.connection_stats.clone(),
+            },
+            connections::limit_requests,
+        ))
+        .layer(middleware)
 }
 
 #[derive(Deserialize)]
 pub struct GithubParams {
+    /// The ref to analyse, `HEAD` by default
+    #[serde(rename = "ref")]
+    pub ref_name: Option<String>,
+    /// Full id of a commit to analyse on its own, instead of the history of the ref
+    pub commit: Option<String>,
+    /// Preset of the server's config file, giving the `path_filter`, `exclude`, `x_coord`,
+    /// `y_coord`, `count_kinds`, `palette`, `width` and `height` the query doesn't. See [`Params`].
+    pub preset: Option<String>,
     pub path_filter: Option<String>,
+    pub exclude: Option<String>,
+    pub ignore_file: Option<bool>,
+    /// Skip the directories listed in [`analysis::VENDORED_DIRS`]
+    #[serde(default)]
+    pub skip_vendored: bool,
+    /// Directory of the repository to analyse, like `rust/agent`
+    pub subdir: Option<String>,
+    /// Expand the local `macro_rules!` macros before counting, which is approximate
+    #[serde(default)]
+    pub expand_local_macros: bool,
+    /// Leave out the commits before the first one with matching files, `true` by default
+    pub trim_empty: Option<bool>,
     #[serde(default)]
     pub x_coord: XCoord,
+    /// Comma-separated refs to plot next to `ref`, each with a line of its own. The commits they
+    /// share with `ref` or with one another are drawn once, and `x_coord` defaults to `Date`.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub compare_ref: Vec<String>,
+    /// Comma-separated metrics, drawn together when there are several of them
+    #[serde(default = "default_y_coords", deserialize_with = "comma_separated")]
+    pub y_coord: Vec<YCoord>,
+    /// Comma-separated counters summed into the first of the metrics instead, like
+    /// `Functions,Methods`, which is what `Functions` counts
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub count_kinds: Vec<CountKind>,
+    /// Draw each of the metrics as a chart of its own, stacked from top to bottom
+    #[serde(default)]
+    pub subplots: bool,
+    /// Leave out the safe counts
     #[serde(default)]
-    pub y_coord: YCoord,
+    pub unsafe_only: bool,
+    #[serde(default)]
+    pub mode: PlotMode,
+    /// Moving average window, in commits
+    pub smooth: Option<u32>,
+    #[serde(default)]
+    pub smooth_only: bool,
+    #[serde(default)]
+    pub ratio_axis: bool,
+    /// Draw the number of files that could not be analysed, `true` by default
+    pub show_failures: Option<bool>,
+    /// Mark each commit on the lines, unless there are too many of them
+    #[serde(default)]
+    pub markers: bool,
+    /// Draw the lines as steps from commit to commit
+    #[serde(default)]
+    pub step: bool,
+    /// Also draw the total of the safe and the unsafe counts
+    #[serde(default)]
+    pub total: bool,
+    /// Count to draw a dashed line at
+    pub threshold: Option<i64>,
+    /// Unsafe share to draw a dashed line at, in percent
+    pub ratio_threshold: Option<f64>,
+    #[serde(default)]
+    pub palette: Palette,
+    /// Timezone of the dates on the X axis, `utc`, `commit` or an offset like `+02:00`
+    #[serde(default)]
+    pub tz: DateTimezone,
+    /// Draw the caption describing what the chart was made from, `true` by default
+    pub caption: Option<bool>,
+    /// `Sparkline` or `SparklineArea` for a small line without the axes, the default of the
+    /// `/sparkline` endpoint
+    #[serde(default)]
+    pub style: PlotStyle,
+    /// Width of the chart in pixels, in [`IMAGE_SIZE_RANGE`]
+    pub width: Option<u32>,
+    /// Height of the chart in pixels, in [`IMAGE_SIZE_RANGE`]
+    pub height: Option<u32>,
+    /// Make the browsers save the chart to a file instead of showing it, `1` or `true`
+    #[serde(default, deserialize_with = "flag")]
+    pub download: bool,
+}
+
+fn default_y_coords() -> Vec<YCoord> {
+    vec![YCoord::default()]
+}
+
+/// The [`GithubParams`] of the query, with the preset it names applied
+///
+/// The long regexes of a preset don't have to be URL-encoded into every link to the chart.
+struct Params(GithubParams);
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for Params {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        GithubParams::from_uri(&parts.uri, &state.presets)
+            .map(Params)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    }
+}
+
+/// A boolean that can also be spelled `1` and `0`, as is common for the flags in URLs
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match String::deserialize(deserializer)?.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(other),
+            &"1, 0, true or false",
+        )),
+    }
+}
+
+/// The `Content-Disposition` making the browsers save the output of `repo` to a file
+fn attachment(repo: &GithubRepo, extension: &str) -> [(HeaderName, String); 1] {
+    // the validated names need no escaping
+    [(
+        header::CONTENT_DISPOSITION,
+        format!(
+            "attachment; filename=\"{}-{}-unsafe.{}\"",
+            repo.owner, repo.name, extension
+        ),
+    )]
+}
+
+fn comma_separated<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(',')
+        .map(|item| T::deserialize(item.into_deserializer()))
+        .collect()
+}
+
+impl GithubParams {
+    /// Parses the query of `uri`, with the values of its `preset` for the parameters it doesn't give
+    fn from_uri(uri: &Uri, presets: &Config) -> Result<Self, String> {
+        let Query(mut params) =
+            Query::<GithubParams>::try_from_uri(uri).map_err(|e| e.body_text())?;
+        let Query(query) =
+            Query::<HashMap<String, String>>::try_from_uri(uri).map_err(|e| e.body_text())?;
+        let given = |key: &str| query.contains_key(key);
+        let preset = match &params.preset {
+            Some(name) => Some(presets.preset(name)?),
+            None => None,
+        };
+
+        // the commits of the compared refs are numbered separately, their dates line up
+        let x_coord_chosen = given("x_coord") || preset.is_some_and(|p| p.x_coord.is_some());
+        if !params.compare_ref.is_empty() && !x_coord_chosen {
+            params.x_coord = XCoord::Date;
+        }
+        let Some(preset) = preset else {
+            return Ok(params);
+        };
+        preset.apply(
+            PresetOptions {
+                filter: &mut params.path_filter,
+                exclude: &mut params.exclude,
+                x_coord: &mut params.x_coord,
+                y_coord: &mut params.y_coord,
+                count_kinds: &mut params.count_kinds,
+                palette: &mut params.palette,
+                width: &mut params.width,
+                height: &mut params.height,
+            },
+            // the filter is the `path_filter` of the query
+            |name| {
+                given(if name == "filter" {
+                    "path_filter"
+                } else {
+                    name
+                })
+            },
+        );
+        Ok(params)
+    }
+
+    /// The metrics to draw, with the `count_kinds` applied to the first one
+    fn y_coords(&self) -> Vec<YCoord> {
+        let mut y_coords = self.y_coord.clone();
+        if !self.count_kinds.is_empty() {
+            y_coords[0] = YCoord::from_count_kinds(self.count_kinds.iter().copied().collect());
+        }
+        y_coords
+    }
+
+    fn check_commit(&self) -> Result<(), String> {
+        match &self.commit {
+            Some(_) if self.ref_name.is_some() => {
+                Err("ref and commit can't be used together".to_string())
+            }
+            Some(commit) if analysis::parse_commit_id(commit).is_none() => {
+                Err("commit must be the full id of a commit".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Analyses the repository, re-using the cached result if the ref hasn't moved since
+///
+/// Blocks, so it should be called from `spawn_blocking`.
+fn analyse(
+    state: &AppState,
+    repo: &GithubRepo,
+    key: AnalysisKey,
+    should_interrupt: &AtomicBool,
+) -> Result<Arc<RepoAnalysis>, (StatusCode, String)> {
+    let mut analyses = analyse_refs(state, repo, key, &[], should_interrupt)?;
+    Ok(analyses.remove(0))
+}
+
+/// Like [`analyse`], for the ref of `key` followed by the `compare_refs`
+///
+/// Unless all of them are cached, they are fetched in a single pack and their shared history is
+/// analysed once.
+fn analyse_refs(
+    state: &AppState,
+    repo: &GithubRepo,
+    key: AnalysisKey,
+    compare_refs: &[String],
+    should_interrupt: &AtomicBool,
+) -> Result<Vec<Arc<RepoAnalysis>>, (StatusCode, String)> {
+    let url = state.repo_url(repo);
+    let keys = std::iter::once(key.clone())
+        .chain(compare_refs.iter().map(|ref_name| AnalysisKey {
+            ref_name: ref_name.clone(),
+            ..key.clone()
+        }))
+        .collect::<Vec<_>>();
+    let wanted_refs = std::iter::once(key.ref_name.as_str())
+        .chain(compare_refs.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+
+    // listing the refs is cheap next to fetching the whole history again
+    let tips = analysis::resolve_remote_refs(&url, &wanted_refs, &state.limits, should_interrupt)
+        .map_err(|e| analysis_failure(state, &key, e))?;
+    let keys = keys
+        .into_iter()
+        .zip(tips)
+        .map(|(analysis, tip)| ResultKey { analysis, tip })
+        .collect::<Vec<_>>();
+    let cached = keys
+        .iter()
+        .map(|key| state.results.get(key))
+        .collect::<Option<Vec<_>>>();
+    if let Some(analyses) = cached {
+        return Ok(analyses);
+    }
+
+    let analyses = analysis::analyse_repo_refs(
+        &state.blob_analysis_cache,
+        &url,
+        &wanted_refs,
+        key.path_filter(),
+        &state.limits,
+        should_interrupt,
+    )
+    .map_err(|e| analysis_failure(state, &key, e))?;
+    state.failures.invalidate(&key.repo);
+    Ok(keys
+        .into_iter()
+        .zip(analyses)
+        .map(|(key, analysis)| {
+            let analysis = Arc::new(analysis);
+            state.results.insert(key.clone(), analysis.clone());
+            state
+                .analysed_repos
+                .insert(&key, url.clone(), analysis.commits.len());
+            analysis
+        })
+        .collect())
+}
+
+/// Picks the status of the failed analysis, remembering the failures of the repository itself
+fn analysis_failure(state: &AppState, key: &AnalysisKey, e: AnalysisError) -> (StatusCode, String) {
+    let status = match e {
+        AnalysisError::RefNotFound(_)
+        | AnalysisError::CommitNotFetchable(_)
+        | AnalysisError::AuthenticationRequired => StatusCode::NOT_FOUND,
+        AnalysisError::RepositoryTooLarge { .. } | AnalysisError::MemoryBudgetExceeded { .. } => {
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        AnalysisError::TooManyBlobs { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        AnalysisError::Interrupted => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    if let AnalysisError::MemoryBudgetExceeded { needed, .. } = e {
+        warn!(
+            "The analysis of {} went over the memory budget, at about {} bytes",
+            key.repo, needed
+        );
+        let mut over_memory_budget = state.over_memory_budget.lock().unwrap();
+        *over_memory_budget.entry(key.repo.clone()).or_default() += 1;
+    }
+    // these are about the repository itself, whatever the ref or the filters
+    if matches!(
+        e,
+        AnalysisError::AuthenticationRequired | AnalysisError::RepositoryTooLarge { .. }
+    ) {
+        state.failures.insert(
+            key.repo.clone(),
+            CachedFailure {
+                status,
+                message: e.to_string(),
+                at: Instant::now(),
+            },
+        );
+    }
+    (status, e.to_string())
+}
+
+/// The chart drawn as a sparkline, unless the query asks for the filled one
+async fn sparkline(
+    state: State<AppState>,
+    path: Path<(String, String)>,
+    Params(mut params): Params,
+) -> Result<impl IntoResponse, Response> {
+    if !params.style.is_sparkline() {
+        params.style = PlotStyle::Sparkline;
+    }
+    github(state, path, Params(params)).await
 }
 
 async fn github(
-    State(AppState {
-        blob_analysis_cache,
-    }): State<AppState>,
+    State(state): State<AppState>,
     Path((owner, repo)): Path<(String, String)>,
-    Query(params): Query<GithubParams>,
-) -> (TypedHeader<ContentType>, TypedHeader<CacheControl>, String) {
-    let url = format!("https://github.com/{}/{}", owner, repo);
-    let path_filter = Regex::new(&params.path_filter.unwrap_or(r"\.rs$".to_string())).unwrap();
+    Params(params): Params,
+) -> Result<
+    (
+        TypedHeader<ContentType>,
+        TypedHeader<CacheControl>,
+        [(&'static str, String); 2],
+        [(&'static str, String); 4],
+        Option<[(HeaderName, String); 1]>,
+        String,
+    ),
+    Response,
+> {
+    let repo = GithubRepo::parse(&owner, &repo)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()).into_response())?;
+    let key = AnalysisKey::new(&repo, &params);
 
-    let span = Span::current();
+    if let Some(window) = params.smooth {
+        if !(1..=MAX_SMOOTHING_WINDOW).contains(&window) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("smooth must be between 1 and {}", MAX_SMOOTHING_WINDOW),
+            )
+                .into_response());
+        }
+    }
+    for (name, size) in [("width", params.width), ("height", params.height)] {
+        if size.is_some_and(|size| !IMAGE_SIZE_RANGE.contains(&size)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "{} must be between {} and {}",
+                    name,
+                    IMAGE_SIZE_RANGE.start(),
+                    IMAGE_SIZE_RANGE.end()
+                ),
+            )
+                .into_response());
+        }
+    }
 
-    // TODO: return error
-    // TODO: cache
-    let rendered = tokio::task::spawn_blocking(move || {
-        // connect the parent manually
-        let _span = info_span!(parent: &span, "blocking_analysis", url = %url).entered();
+    params
+        .check_commit()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    key.check_regexes()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
 
-        let results = analysis::analyse_repo(&blob_analysis_cache, &url, path_filter);
+    let y_coords = params.y_coords();
+    let mut plot_options = PlotOptions::default();
+    plot_options.x_coord = params.x_coord;
+    plot_options.y_coord = y_coords[0];
+    plot_options.extra_y_coords = y_coords[1..].to_vec();
+    plot_options.subplots = params.subplots;
+    plot_options.unsafe_only = params.unsafe_only;
+    plot_options.mode = params.mode;
+    plot_options.smooth = params.smooth;
+    plot_options.smooth_only = params.smooth_only;
+    plot_options.ratio_axis = params.ratio_axis;
+    plot_options.show_failures = params.show_failures.unwrap_or(true);
+    plot_options.markers = params.markers;
+    plot_options.step = params.step;
+    plot_options.show_total = params.total;
+    plot_options.threshold = params.threshold;
+    plot_options.ratio_threshold = params.ratio_threshold;
+    plot_options.palette = params.palette;
+    plot_options.timezone = params.tz;
+    plot_options.style = params.style;
+    plot_options.width = params.width;
+    plot_options.height = params.height;
 
-        plot::plot_results_svg(&results, params.x_coord, params.y_coord)
-    })
-    .await
-    .unwrap();
+    let compare_refs = params.compare_ref.clone();
+    let ref_names = std::iter::once(key.ref_name.clone())
+        .chain(compare_refs.iter().cloned())
+        .collect::<Vec<_>>();
+    let metadata = params.caption.unwrap_or(true).then(|| ChartMetadata {
+        expanded_local_macros: key.expand_local_macros,
+        ..ChartMetadata::new(
+            &repo.url(),
+            &ref_names.join(" vs "),
+            &key.path_filter,
+            key.exclude.as_deref(),
+        )
+    });
 
-    (
+    let trim_empty = params.trim_empty.unwrap_or(true);
+    let disposition = params.download.then(|| attachment(&repo, "svg"));
+    let failure_key = key.clone();
+    let (rendered, skipped_commits, timings, tips) =
+        run_blocking(state, &failure_key, move |state, should_interrupt| {
+            let mut analyses = analyse_refs(state, &repo, key, &compare_refs, should_interrupt)?;
+            // the match stats cover the whole pack, the history of a ref can still have no matching
+            // files when the ones of the others do
+            if analyses[0].match_stats.matched_blobs == 0 {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    analyses[0].match_stats.no_matches_report(),
+                ));
+            }
+
+            if trim_empty {
+                analyses = analyses.into_iter().map(trimmed).collect();
+            }
+            let analysis = &analyses[0];
+            let metadata = metadata.map(|metadata| ChartMetadata {
+                tip: Some(analysis.head),
+                ..metadata
+            });
+            let rendered = match analyses.as_slice() {
+                [analysis] => {
+                    plot::plot_results_svg(&analysis.commits, metadata.as_ref(), &plot_options)
+                }
+                analyses => {
+                    let histories = ref_names
+                        .iter()
+                        .zip(analyses)
+                        .map(|(name, analysis)| (name.as_str(), analysis.commits.as_slice()))
+                        .collect::<Vec<_>>();
+                    plot::plot_comparison_svg(&histories, metadata.as_ref(), &plot_options)
+                }
+            }
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            Ok((
+                rendered,
+                analysis.skipped_commits,
+                analysis.timings.clone(),
+                analysed_tips(&analyses),
+            ))
+        })
+        .await?;
+
+    Ok((
         TypedHeader(mime::IMAGE_SVG.into()),
         TypedHeader(CacheControl::new().with_no_cache()),
+        [
+            // the commits that could not be parsed are missing from the chart
+            ("X-Skipped-Commits", skipped_commits.to_string()),
+            ("X-Analysed-Tip", tips),
+        ],
+        timing_headers(&timings),
+        disposition,
         rendered,
-    )
+    ))
+}
+
+/// Leaves out the commits before the first one with matching files
+fn trimmed(analysis: Arc<RepoAnalysis>) -> Arc<RepoAnalysis> {
+    // the cached analysis is shared, trim a copy
+    let mut analysis = RepoAnalysis::clone(&analysis);
+    analysis.trim_empty();
+    Arc::new(analysis)
+}
+
+/// The commits the analysed histories end at, so that a saved response can be traced back to the
+/// exact revisions, in the order of the refs
+fn analysed_tips(analyses: &[Arc<RepoAnalysis>]) -> String {
+    analyses
+        .iter()
+        .map(|analysis| analysis.head.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The phases of the analysis the response was made from, which is the cached one if there was one
+fn timing_headers(timings: &Timings) -> [(&'static str, String); 4] {
+    [
+        ("X-Unsafe-Track-Download-Ms", timings.download.as_millis()),
+        ("X-Unsafe-Track-Indexing-Ms", timings.indexing.as_millis()),
+        ("X-Unsafe-Track-Planning-Ms", timings.planning.as_millis()),
+        (
+            "X-Unsafe-Track-Analyse-Ms",
+            timings.blob_analysis.as_millis(),
+        ),
+    ]
+    .map(|(name, ms)| (name, ms.to_string()))
+}
+
+/// Runs `work` on the repository of `key` on a blocking thread, within the analysis timeout
+async fn run_blocking<T: Send + 'static>(
+    state: AppState,
+    key: &AnalysisKey,
+    work: impl FnOnce(&AppState, &AtomicBool) -> Result<T, (StatusCode, String)> + Send + 'static,
+) -> Result<T, Response> {
+    // don't hit the network again for a repository that was just found missing
+    if let Some(failure) = state.failures.get(&key.repo) {
+        let remaining = FAILURE_CACHE_TTL.saturating_sub(failure.at.elapsed());
+        return Err((
+            failure.status,
+            [("X-Failure-Cache-TTL", remaining.as_secs().to_string())],
+            failure.message,
+        )
+            .into_response());
+    }
+
+    // connect the parent manually
+    let span = info_span!(
+        parent: &Span::current(),
+        "blocking_analysis",
+        repo = %key.repo,
+        git_ref = %key.ref_name
+    );
+    let analysis_timeout = state.analysis_timeout;
+    let should_interrupt = Arc::new(AtomicBool::new(false));
+
+    let handle = tokio::task::spawn_blocking({
+        let should_interrupt = should_interrupt.clone();
+        move || {
+            let _span = span.entered();
+            work(&state, &should_interrupt)
+        }
+    });
+
+    match tokio::time::timeout(analysis_timeout, handle).await {
+        Ok(result) => result.unwrap().map_err(IntoResponse::into_response),
+        Err(_) => {
+            // the blocking task can't be aborted, tell it to stop on its own
+            should_interrupt.store(true, Ordering::Relaxed);
+            Err((
+                StatusCode::GATEWAY_TIMEOUT,
+                format!(
+                    "The analysis took longer than {} seconds",
+                    analysis_timeout.as_secs()
+                ),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// What `/stats` reports, the counts of the connections next to the ones of the cache
+#[derive(Serialize)]
+struct ServerStats {
+    #[serde(flatten)]
+    cache: CacheStats,
+    connections: ConnectionStats,
+    /// The repositories whose analyses went over the memory budget, with how many of them did
+    over_memory_budget: BTreeMap<String, u64>,
+}
+
+async fn stats(
+    State(AppState {
+        blob_analysis_cache,
+        connection_stats,
+        over_memory_budget,
+        ..
+    }): State<AppState>,
+) -> Json<ServerStats> {
+    Json(ServerStats {
+        cache: blob_analysis_cache.stats(),
+        connections: connection_stats.snapshot(),
+        over_memory_budget: over_memory_budget.lock().unwrap().clone(),
+    })
+}
+
+/// All the logical CPUs but two, at least one
+fn default_threads() -> NonZeroUsize {
+    let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    NonZeroUsize::new(cpus.saturating_sub(2)).unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Logs the cache health once in a while, for the servers nobody polls `/admin/cache-stats` of
+async fn log_cache_stats(cache: AnalysisCache) {
+    let mut interval = tokio::time::interval(CACHE_STATS_INTERVAL);
+    // the first tick completes right away, with nothing cached yet
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let stats = cache.stats();
+        info!(
+            entry_count = stats.entry_count,
+            approximate_memory_bytes = stats.approximate_memory_bytes,
+            hits = stats.hits,
+            misses = stats.misses,
+            hit_rate = stats.hit_rate,
+            evictions = stats.evictions,
+            tree_entry_count = stats.tree_entry_count,
+            pack_entry_count = stats.pack_entry_count,
+            "Analysis cache stats"
+        );
+    }
+}
+
+/// The part of the GitHub push event payload we care about
+#[derive(Deserialize)]
+struct PushPayload {
+    repository: PushRepository,
+}
+
+#[derive(Deserialize)]
+struct PushRepository {
+    full_name: String,
+}
+
+/// Checks the `X-Hub-Signature-256` header, which is the hex HMAC-SHA256 of the body prefixed with `sha256=`
+fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let Some(signature) = headers
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("sha256="))
+        .and_then(|v| hex::decode(v).ok())
+    else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("BUG: HMAC accepts keys of any size");
+    mac.update(body);
+    // constant-time comparison
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Evicts the cached results of the pushed repository and analyses it again in the background
+///
+/// The results of the old tips would not be served anyway, this frees their space and makes the
+/// next request find the new ones ready.
+async fn github_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let Some(secret) = &state.webhook_secret else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "The webhook is not configured".to_string(),
+        ));
+    };
+
+    if !verify_signature(secret, &headers, &body) {
+        return Err((StatusCode::UNAUTHORIZED, "Signature mismatch".to_string()));
+    }
+
+    // sent when the webhook is created
+    if headers.get("X-GitHub-Event").is_some_and(|e| e == "ping") {
+        return Ok(StatusCode::OK);
+    }
+
+    let payload: PushPayload = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Malformed payload: {}", e)))?;
+    let repo = payload
+        .repository
+        .full_name
+        .split_once('/')
+        .and_then(|(owner, name)| GithubRepo::parse(owner, name).ok())
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Malformed repository name {}", payload.repository.full_name),
+            )
+        })?;
+
+    let evicted = state
+        .results
+        .iter()
+        .map(|(key, _)| key)
+        .filter(|key| key.analysis.repo == repo.key())
+        .collect::<Vec<_>>();
+    // this is a no-op for the repositories we've never analysed
+    for key in &evicted {
+        state.results.invalidate(key.as_ref());
+    }
+    info!("Evicted {} cached results of {}", evicted.len(), repo);
+    // the results of several tips of a ref are analysed again only once
+    let evicted = evicted
+        .iter()
+        .map(|key| key.analysis.clone())
+        .collect::<HashSet<_>>();
+    // the push means the repository is there
+    state.failures.invalidate(&repo.key());
+
+    let span = Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _span = info_span!(parent: &span, "webhook_reanalysis", repo = %repo).entered();
+
+        // these have been analysed in time before, so they are not raced against the timeout
+        let should_interrupt = AtomicBool::new(false);
+        for key in evicted {
+            if let Err((_, e)) = analyse(&state, &repo, key, &should_interrupt) {
+                warn!("Re-analysis of {} failed: {}", repo, e);
+            }
+        }
+    });
+
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn state() -> AppState {
+        AppState::new(
+            AnalysisCache::new(16 << 20),
+            AnalysisLimits::default(),
+            Config::default(),
+            None,
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn preset_params() {
+        let presets: Config =
+            toml::from_str("[presets.opus]\nfilter = 'celt/'\nwidth = 1200\n").unwrap();
+        let params = |query: &str| {
+            let uri = format!("/github/owner/repo?{}", query).parse().unwrap();
+            GithubParams::from_uri(&uri, &presets)
+        };
+
+        let from_preset = params("preset=opus").unwrap();
+        assert_eq!(from_preset.path_filter.as_deref(), Some("celt/"));
+        assert_eq!(from_preset.width, Some(1200));
+        let given = params("preset=opus&path_filter=silk/&width=600").unwrap();
+        assert_eq!(given.path_filter.as_deref(), Some("silk/"));
+        assert_eq!(given.width, Some(600));
+
+        let e = params("preset=dav1d").err().unwrap();
+        assert_eq!(e, "Unknown preset `dav1d`, the available ones are: opus");
+    }
+
+    fn app(state: AppState) -> Router {
+        router(state, 8 * 1024, 1024 * 1024, Duration::from_secs(60))
+    }
+
+    /// The routes over the fixture repository, served as `owner/fixture` from `dir`
+    fn fixture_app(dir: &std::path::Path) -> Router {
+        crate::fixture::clone_fixture(&dir.join("owner"), "fixture");
+        plot::register_font(None);
+        let mut state = state();
+        state.url_prefix = format!("{}/", dir.display()).into();
+        app(state)
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, body)
+    }
+
+    async fn get(app: Router, uri: &str) -> (StatusCode, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let (status, _, body) = send(app, request).await;
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn get_gzip(uri: &str) -> Request<Body> {
+        Request::get(uri)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn compressed_chart() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = fixture_app(dir.path());
+        // without the caption, which has the time the chart was made
+        let uri = "/github/owner/fixture?caption=false";
+
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let (status, headers, identity) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+
+        let (status, headers, compressed) = send(app, get_gzip(uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(headers[header::CONTENT_TYPE], "image/svg+xml");
+        let mut svg = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(compressed.as_ref()),
+            &mut svg,
+        )
+        .unwrap();
+        assert!(svg.len() > compressed.len());
+        assert_eq!(svg, identity);
+    }
+
+    #[tokio::test]
+    async fn uncompressed_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = fixture_app(dir.path());
+
+        let (status, headers, body) = send(app, get_gzip("/github/owner/fixture/metrics")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("unsafe_track_"));
+    }
+
+    // the error of the invalid regex is the expected body
+    #[allow(clippy::invalid_regex)]
+    #[tokio::test]
+    async fn invalid_filter_regex() {
+        let expected = Regex::new("[").unwrap_err().to_string();
+        for param in ["path_filter", "exclude"] {
+            // checked before the repository is fetched
+            let (status, body) =
+                get(app(state()), &format!("/github/owner/repo?{}=%5B", param)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", param);
+            assert_eq!(body, expected, "{}", param);
+        }
+    }
 }
@@ -0,0 +1,450 @@
+//! Accepting the connections, with the limits that keep slow or greedy clients from holding on to
+//! them, as every request can take an analysis
+
+use axum::extract::{Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+/// Limits of the connections, applied before the requests reach the router
+#[derive(Debug, Clone)]
+pub struct ConnectionLimits {
+    /// Connections served at once, the others wait in the listen backlog
+    pub max_connections: usize,
+    /// Time to send the headers of a request, from its first byte
+    pub header_timeout: Duration,
+    /// Time a connection is kept without a request being served, before its first request too
+    pub idle_timeout: Duration,
+    /// Size of the request line and the headers
+    pub max_header_bytes: usize,
+}
+
+/// Counts of the connections and the requests cut short by the limits, since the server started
+#[derive(Debug, Default, Serialize)]
+pub struct ConnectionStats {
+    /// Connections being served now
+    pub open_connections: AtomicU64,
+    /// Connections closed for sending the headers of a request too slowly
+    pub header_timeouts: AtomicU64,
+    /// Connections closed for staying idle
+    pub idle_timeouts: AtomicU64,
+    /// 408, the body of a request came too slowly
+    pub request_timeouts: AtomicU64,
+    /// 413, a request had a body too large, or one at all on a route taking none
+    pub payload_too_large: AtomicU64,
+    /// 414, the query of a request was too long
+    pub uri_too_long: AtomicU64,
+    /// 431, the headers of a request were too large
+    pub headers_too_large: AtomicU64,
+}
+
+impl ConnectionStats {
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The counts as they are now, which the counting goes on without
+    pub fn snapshot(&self) -> Self {
+        let load = |counter: &AtomicU64| AtomicU64::new(counter.load(Ordering::Relaxed));
+        Self {
+            open_connections: load(&self.open_connections),
+            header_timeouts: load(&self.header_timeouts),
+            idle_timeouts: load(&self.idle_timeouts),
+            request_timeouts: load(&self.request_timeouts),
+            payload_too_large: load(&self.payload_too_large),
+            uri_too_long: load(&self.uri_too_long),
+            headers_too_large: load(&self.headers_too_large),
+        }
+    }
+}
+
+/// A listener of the connections to serve
+pub trait Accept {
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = std::io::Result<Self::Io>> + Send;
+}
+
+impl Accept for tokio::net::TcpListener {
+    type Io = tokio::net::TcpStream;
+
+    async fn accept(&self) -> std::io::Result<Self::Io> {
+        let (stream, _) = tokio::net::TcpListener::accept(self).await?;
+        Ok(stream)
+    }
+}
+
+#[cfg(unix)]
+impl Accept for tokio::net::UnixListener {
+    type Io = tokio::net::UnixStream;
+
+    async fn accept(&self) -> std::io::Result<Self::Io> {
+        let (stream, _) = tokio::net::UnixListener::accept(self).await?;
+        Ok(stream)
+    }
+}
+
+/// When a connection last had no request in flight
+struct Activity {
+    in_flight: AtomicUsize,
+    idle_since: Mutex<Instant>,
+}
+
+impl Activity {
+    fn idle_until(&self, idle_timeout: Duration) -> Option<Instant> {
+        (self.in_flight.load(Ordering::Relaxed) == 0)
+            .then(|| *self.idle_since.lock().unwrap() + idle_timeout)
+    }
+}
+
+/// Serves `app` on the connections of `listener` until `shutdown` completes
+pub async fn serve(
+    listener: &impl Accept,
+    app: Router,
+    limits: ConnectionLimits,
+    stats: Arc<ConnectionStats>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(limits.header_timeout)
+        .max_buf_size(limits.max_header_bytes);
+    let builder = Arc::new(builder);
+    let permits = Arc::new(Semaphore::new(limits.max_connections));
+
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = async {
+                // not accepting until a connection is closed, the clients wait in the backlog
+                let permit = permits.clone().acquire_owned().await.expect("BUG: semaphore closed");
+                listener.accept().await.map(|stream| (stream, permit))
+            } => accepted,
+            () = &mut shutdown => break,
+        };
+        let (stream, permit) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // like running out of file descriptors, which takes a while to get better
+                warn!("Failed to accept a connection: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let builder = builder.clone();
+        let app = app.clone();
+        let stats = stats.clone();
+        let idle_timeout = limits.idle_timeout;
+        tokio::spawn(async move {
+            let _permit = permit;
+            ConnectionStats::count(&stats.open_connections);
+            serve_connection(&builder, stream, app, idle_timeout, &stats).await;
+            stats.open_connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+async fn serve_connection(
+    builder: &auto::Builder<TokioExecutor>,
+    stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    app: Router,
+    idle_timeout: Duration,
+    stats: &ConnectionStats,
+) {
+    let activity = Arc::new(Activity {
+        in_flight: AtomicUsize::new(0),
+        idle_since: Mutex::new(Instant::now()),
+    });
+    let service = hyper::service::service_fn({
+        let activity = activity.clone();
+        move |request: Request<Incoming>| {
+            let app = app.clone();
+            let activity = activity.clone();
+            activity.in_flight.fetch_add(1, Ordering::Relaxed);
+            async move {
+                let response = app.oneshot(request).await;
+                // the body of the response may still be sent, which the shutdown waits for
+                *activity.idle_since.lock().unwrap() = Instant::now();
+                activity.in_flight.fetch_sub(1, Ordering::Relaxed);
+                response
+            }
+        }
+    });
+
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+    tokio::pin!(connection);
+    // when an idle connection that didn't shut down gracefully is dropped
+    let mut shutdown_deadline = None;
+    let result = loop {
+        // rechecked once the requests in flight are served
+        let deadline = shutdown_deadline.unwrap_or_else(|| {
+            activity
+                .idle_until(idle_timeout)
+                .unwrap_or_else(|| Instant::now() + idle_timeout)
+        });
+        tokio::select! {
+            result = connection.as_mut() => break result,
+            () = tokio::time::sleep_until(deadline) => {
+                if shutdown_deadline.is_some() {
+                    // no shutting down before the first bytes are read, which tell HTTP/1 and
+                    // HTTP/2 apart, or a response taking that long to be read
+                    debug!("Dropping a connection that didn't shut down");
+                    return;
+                }
+                if activity.idle_until(idle_timeout).is_some_and(|until| until <= Instant::now()) {
+                    ConnectionStats::count(&stats.idle_timeouts);
+                    connection.as_mut().graceful_shutdown();
+                    shutdown_deadline = Some(Instant::now() + idle_timeout);
+                }
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        match e.downcast_ref::<hyper::Error>() {
+            Some(e) if is_header_timeout(e) => ConnectionStats::count(&stats.header_timeouts),
+            // answered with a 431 by hyper
+            Some(e) if e.is_parse_too_large() => ConnectionStats::count(&stats.headers_too_large),
+            _ => {}
+        }
+        debug!("Connection failed: {}", e);
+    }
+}
+
+/// hyper tells the header timeouts apart from the other errors only by their message
+fn is_header_timeout(e: &hyper::Error) -> bool {
+    e.to_string() == "read header from client timeout"
+}
+
+/// Limits of the requests, checked before they are routed
+#[derive(Clone)]
+pub struct RequestLimits {
+    pub max_query_bytes: usize,
+    pub stats: Arc<ConnectionStats>,
+}
+
+/// Rejects the queries too long and the bodies sent to the routes taking none, and counts the
+/// requests cut short by the limits of the routes
+pub async fn limit_requests(
+    State(limits): State<RequestLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let query_bytes = request.uri().query().map_or(0, str::len);
+    let response = if query_bytes > limits.max_query_bytes {
+        (
+            StatusCode::URI_TOO_LONG,
+            format!(
+                "The query is {} bytes long, more than the {} allowed",
+                query_bytes, limits.max_query_bytes
+            ),
+        )
+            .into_response()
+    } else if matches!(*request.method(), Method::GET | Method::HEAD) && has_body(&request) {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "GET requests take no body".to_string(),
+        )
+            .into_response()
+    } else {
+        next.run(request).await
+    };
+
+    let counter = match response.status() {
+        StatusCode::REQUEST_TIMEOUT => &limits.stats.request_timeouts,
+        StatusCode::PAYLOAD_TOO_LARGE => &limits.stats.payload_too_large,
+        StatusCode::URI_TOO_LONG => &limits.stats.uri_too_long,
+        _ => return response,
+    };
+    ConnectionStats::count(counter);
+    response
+}
+
+fn has_body(request: &Request) -> bool {
+    let headers = request.headers();
+    headers.contains_key(header::TRANSFER_ENCODING)
+        || headers
+            .get(header::CONTENT_LENGTH)
+            .is_some_and(|length| length != "0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, Bytes};
+    use axum::routing::{get, post};
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tower_http::timeout::TimeoutLayer;
+
+    const MAX_QUERY_BYTES: usize = 64;
+    const BODY_TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn app(stats: Arc<ConnectionStats>) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route(
+                "/upload",
+                post(|_: Bytes| async {}).layer(TimeoutLayer::new(BODY_TIMEOUT)),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                RequestLimits {
+                    max_query_bytes: MAX_QUERY_BYTES,
+                    stats,
+                },
+                limit_requests,
+            ))
+    }
+
+    fn limits() -> ConnectionLimits {
+        ConnectionLimits {
+            max_connections: 4,
+            header_timeout: Duration::from_millis(300),
+            idle_timeout: Duration::from_secs(30),
+            // the least hyper accepts
+            max_header_bytes: 8192,
+        }
+    }
+
+    /// Serves [`app`] on a port of the loopback interface, until the runtime of the test is dropped
+    async fn listen(limits: ConnectionLimits) -> (SocketAddr, Arc<ConnectionStats>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let stats = Arc::<ConnectionStats>::default();
+        let app = app(stats.clone());
+        let serve_stats = stats.clone();
+        tokio::spawn(async move {
+            serve(&listener, app, limits, serve_stats, std::future::pending()).await;
+        });
+        (address, stats)
+    }
+
+    /// Waits for `counter` to reach `expected`, it's counted once the connection is closed
+    async fn counted(counter: &AtomicU64, expected: u64) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while counter.load(Ordering::Relaxed) != expected {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("counted {}", counter.load(Ordering::Relaxed)));
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> StatusCode {
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn slow_headers() {
+        let limits = limits();
+        let header_timeout = limits.header_timeout;
+        let (address, stats) = listen(limits).await;
+        let (mut reader, mut writer) = TcpStream::connect(address).await.unwrap().into_split();
+        let started = Instant::now();
+        writer.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        // a byte of a header every 100ms, the headers never end
+        tokio::spawn(async move {
+            while writer.write_all(b"x").await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), reader.read_to_end(&mut response))
+            .await
+            .expect("The connection wasn't closed")
+            .ok();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= header_timeout, "{:?}", elapsed);
+        assert!(elapsed < header_timeout * 4, "{:?}", elapsed);
+        counted(&stats.header_timeouts, 1).await;
+    }
+
+    #[tokio::test]
+    async fn headers_too_large() {
+        let (address, stats) = listen(limits()).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nX-Padding: {}\r\n\r\n",
+            "x".repeat(16 * 1024)
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.ok();
+        assert!(
+            response.starts_with(b"HTTP/1.1 431"),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+        counted(&stats.headers_too_large, 1).await;
+    }
+
+    #[tokio::test]
+    async fn query_too_long() {
+        let stats = Arc::<ConnectionStats>::default();
+        let query = "x".repeat(MAX_QUERY_BYTES);
+        let request = Request::get(format!("/?{}", query)).body(Body::empty());
+        assert_eq!(
+            send(app(stats.clone()), request.unwrap()).await,
+            StatusCode::OK
+        );
+        let request = Request::get(format!("/?{}x", query)).body(Body::empty());
+        assert_eq!(
+            send(app(stats.clone()), request.unwrap()).await,
+            StatusCode::URI_TOO_LONG
+        );
+        assert_eq!(stats.uri_too_long.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn get_with_body() {
+        let stats = Arc::<ConnectionStats>::default();
+        let request = Request::get("/")
+            .header(header::CONTENT_LENGTH, "4")
+            .body(Body::from("body"));
+        assert_eq!(
+            send(app(stats.clone()), request.unwrap()).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        // an empty one is as good as none
+        let request = Request::get("/")
+            .header(header::CONTENT_LENGTH, "0")
+            .body(Body::empty());
+        assert_eq!(
+            send(app(stats.clone()), request.unwrap()).await,
+            StatusCode::OK
+        );
+        assert_eq!(stats.payload_too_large.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_body() {
+        let stats = Arc::<ConnectionStats>::default();
+        let body = futures_util::stream::pending::<Result<Bytes, std::io::Error>>();
+        let request = Request::post("/upload").body(Body::from_stream(body));
+        assert_eq!(
+            send(app(stats.clone()), request.unwrap()).await,
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_eq!(stats.request_timeouts.load(Ordering::Relaxed), 1);
+    }
+}
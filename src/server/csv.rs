@@ -0,0 +1,77 @@
+//! The numbers behind a chart, as the rows of the CLI's CSV output
+
+use super::{
+    analyse_refs, analysed_tips, attachment, run_blocking, timing_headers, trimmed, AnalysisKey,
+    AppState, Params,
+};
+use crate::output::{csv_row, CSV_HEADER};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::convert::Infallible;
+use unsafe_track::github::GithubRepo;
+
+const CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+/// Takes the same parameters as the chart, the rows are the commits it would plot
+///
+/// With `compare_ref`, the rows of each ref follow those of the one before, the shared commits
+/// are repeated.
+pub async fn csv(
+    State(state): State<AppState>,
+    Path((owner, repo)): Path<(String, String)>,
+    Params(params): Params,
+) -> Result<Response, Response> {
+    let repo = GithubRepo::parse(&owner, &repo)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()).into_response())?;
+    let key = AnalysisKey::new(&repo, &params);
+    params
+        .check_commit()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    key.check_regexes()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+
+    let compare_refs = params.compare_ref.clone();
+    let ref_names = std::iter::once(key.ref_name.clone())
+        .chain(compare_refs.iter().cloned())
+        .collect::<Vec<_>>();
+    let y_coord = params.y_coords()[0];
+    let trim_empty = params.trim_empty.unwrap_or(true);
+    let disposition = attachment(&repo, "csv");
+    let failure_key = key.clone();
+    let analyses = run_blocking(state, &failure_key, move |state, should_interrupt| {
+        let analyses = analyse_refs(state, &repo, key, &compare_refs, should_interrupt)?;
+        Ok(if trim_empty {
+            analyses.into_iter().map(trimmed).collect()
+        } else {
+            analyses
+        })
+    })
+    .await?;
+
+    let timings = timing_headers(&analyses[0].timings);
+    let tips = [("X-Analysed-Tip", analysed_tips(&analyses))];
+    // the rows are formatted as they are sent, not all at once, the refs one after the other
+    let rows = ref_names
+        .into_iter()
+        .zip(analyses)
+        .flat_map(move |(ref_name, analysis)| {
+            (0..analysis.commits.len())
+                .map(move |i| csv_row(&ref_name, &analysis.commits[i], y_coord))
+        });
+    let body = futures_util::stream::iter(
+        std::iter::once(CSV_HEADER.to_string())
+            .chain(rows)
+            .map(Ok::<_, Infallible>),
+    );
+
+    Ok((
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        timings,
+        tips,
+        disposition,
+        Body::from_stream(body),
+    )
+        .into_response())
+}
@@ -0,0 +1,109 @@
+//! The landing page with a form building the chart URL
+
+use super::default_path_filter;
+use super::view::{enum_multi_options, enum_options, escape_html};
+use axum::response::Html;
+use unsafe_track::plot::palette::Palette;
+use unsafe_track::plot::{CountKind, PlotMode, XCoord, YCoord, MAX_SMOOTHING_WINDOW};
+
+// composes the chart URL from the form, as the repository is a part of the path
+const SCRIPT: &str = r#"
+const form = document.getElementById("builder");
+const preview = document.getElementById("preview");
+const snippet = document.getElementById("snippet");
+const view = document.getElementById("view");
+form.addEventListener("submit", (event) => {
+    event.preventDefault();
+    const repo = form.elements.repo.value.trim().split("/");
+    if (repo.length !== 2 || !repo[0] || !repo[1]) {
+        alert("The repository should look like owner/repo");
+        return;
+    }
+    const params = new URLSearchParams();
+    for (const element of form.elements) {
+        if (!element.name || element.name === "repo") continue;
+        if (element.type === "checkbox") {
+            if (element.checked !== (element.dataset.default === "true")) params.set(element.name, element.checked);
+        } else if (element.type === "select-multiple") {
+            const values = Array.from(element.selectedOptions, (option) => option.value);
+            if (values.length) params.set(element.name, values.join(","));
+        } else if (element.value !== "") {
+            params.set(element.name, element.value);
+        }
+    }
+    const query = params.toString() ? "?" + params.toString() : "";
+    const path = "/github/" + repo.map(encodeURIComponent).join("/");
+    const chart = new URL(path + query, location.href).href;
+    preview.src = chart;
+    preview.hidden = false;
+    snippet.value = "![unsafe usage in " + repo.join("/") + "](" + chart + ")";
+    snippet.hidden = false;
+    view.href = path + "/view" + query;
+    view.hidden = false;
+});
+"#;
+
+pub async fn index() -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>unsafe-track</title>
+</head>
+<body>
+<h1>unsafe-track</h1>
+<p>Charts the usage of <code>unsafe</code> across the history of a GitHub repository.</p>
+<form id="builder">
+<label>Repository <input name="repo" placeholder="owner/repo" required></label>
+<label>Ref <input name="ref" placeholder="HEAD"></label>
+<label>Commit <input name="commit" placeholder="full id, instead of the ref" size="40"></label>
+<label>Compare with <input name="compare_ref" placeholder="other refs, comma-separated"></label>
+<br>
+<label>Path filter <input name="path_filter" placeholder="{default_filter}"></label>
+<label>Exclude <input name="exclude"></label>
+<label><input type="checkbox" name="ignore_file" data-default="true" checked> Use the ignore file</label>
+<label><input type="checkbox" name="skip_vendored" data-default="false"> Skip vendored code</label>
+<br>
+<label>Subdirectory <input name="subdir" placeholder="rust/agent"></label>
+<label><input type="checkbox" name="trim_empty" data-default="true" checked> Trim the commits before it existed</label>
+<label><input type="checkbox" name="expand_local_macros" data-default="false"> Expand local macros (approximate)</label>
+<br>
+<label>X <select name="x_coord">{x_coord}</select></label>
+<label>Y <select name="y_coord" multiple size="3">{y_coord}</select></label>
+<label>Counters of the first Y <select name="count_kinds" multiple size="3">{count_kinds}</select></label>
+<label><input type="checkbox" name="subplots" data-default="false"> Chart each on its own</label>
+<label>Mode <select name="mode">{mode}</select></label>
+<label>Palette <select name="palette">{palette}</select></label>
+<label>Timezone <input name="tz" placeholder="utc, commit or +02:00"></label>
+<label>Smooth <input type="number" name="smooth" min="1" max="{max_smooth}"></label>
+<label><input type="checkbox" name="smooth_only" data-default="false"> Only the average</label>
+<label><input type="checkbox" name="unsafe_only" data-default="false"> Only unsafe</label>
+<label><input type="checkbox" name="ratio_axis" data-default="false"> Unsafe share axis</label>
+<label><input type="checkbox" name="show_failures" data-default="true" checked> Failed files</label>
+<label><input type="checkbox" name="markers" data-default="false"> Commit markers</label>
+<label><input type="checkbox" name="step" data-default="false"> Steps</label>
+<label><input type="checkbox" name="total" data-default="false"> Total</label>
+<label><input type="checkbox" name="caption" data-default="true" checked> Caption</label>
+<label>Threshold <input type="number" name="threshold"></label>
+<label>Share threshold, % <input type="number" name="ratio_threshold" min="0" max="100" step="any"></label>
+<br>
+<button type="submit">Build the chart</button>
+</form>
+<p><a id="view" hidden>Open the interactive view</a></p>
+<p><textarea id="snippet" cols="100" rows="2" readonly hidden onfocus="this.select()"></textarea></p>
+<img id="preview" alt="unsafe usage chart" hidden>
+<script>{script}</script>
+</body>
+</html>
+"#,
+        default_filter = escape_html(default_path_filter()),
+        x_coord = enum_options(&XCoord::default()),
+        count_kinds = enum_multi_options::<CountKind>(&[]),
+        y_coord = enum_options(&YCoord::default()),
+        mode = enum_options(&PlotMode::default()),
+        palette = enum_options(&Palette::default()),
+        max_smooth = MAX_SMOOTHING_WINDOW,
+        script = SCRIPT,
+    ))
+}
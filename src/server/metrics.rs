@@ -0,0 +1,123 @@
+//! The counters of the latest analysed commit in the Prometheus text exposition format, for
+//! charting them over wall-clock time
+
+use super::{analyse, run_blocking, AnalysisKey, AppState, Params};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::fmt::Write;
+use unsafe_track::github::GithubRepo;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The names the counters are exposed under, in the order of `CounterBlock`
+const COUNTERS: &[&str] = &["functions", "expressions", "impls", "traits", "methods"];
+
+pub async fn metrics(
+    State(state): State<AppState>,
+    Path((owner, repo)): Path<(String, String)>,
+    Params(params): Params,
+) -> Result<Response, Response> {
+    let repo = GithubRepo::parse(&owner, &repo)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()).into_response())?;
+    let key = AnalysisKey::new(&repo, &params);
+    params
+        .check_commit()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    key.check_regexes()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+
+    let repo_label = escape_label(&key.repo);
+    let ref_label = escape_label(&key.ref_name);
+    let failure_key = key.clone();
+    let (rendered, tip) = run_blocking(state, &failure_key, move |state, should_interrupt| {
+        let analysis = analyse(state, &repo, key, should_interrupt)?;
+        let mut buf = String::new();
+        // an empty history has no samples, same as a failure
+        let Some(latest) = analysis.commits.last() else {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The history has no commits".to_string(),
+            ));
+        };
+
+        let c = &latest.counters;
+        let counts = [
+            &c.functions,
+            &c.exprs,
+            &c.item_impls,
+            &c.item_traits,
+            &c.methods,
+        ];
+        for (name, count) in COUNTERS.iter().zip(counts) {
+            for (safety, value) in [("unsafe", count.unsafe_), ("safe", count.safe)] {
+                gauge(
+                    &mut buf,
+                    &format!("unsafe_track_{}_{}", safety, name),
+                    &format!("Number of {} {} in the latest commit", safety, name),
+                    &repo_label,
+                    value,
+                );
+            }
+        }
+        gauge(
+            &mut buf,
+            "unsafe_track_matched_files",
+            "Number of files matching the path filter in the latest commit",
+            &repo_label,
+            latest.matched_files_count as u64,
+        );
+        gauge(
+            &mut buf,
+            "unsafe_track_failed_files",
+            "Number of matching files that could not be analysed in the latest commit",
+            &repo_label,
+            latest.failed_files_count as u64,
+        );
+        gauge(
+            &mut buf,
+            "unsafe_track_skipped_commits",
+            "Number of commits that could not be parsed, missing from the history",
+            &repo_label,
+            analysis.skipped_commits as u64,
+        );
+        writeln!(
+            buf,
+            "# HELP unsafe_track_tip_info The analysed ref and the commit it points to\n\
+             # TYPE unsafe_track_tip_info gauge\n\
+             unsafe_track_tip_info{{repo=\"{}\",ref=\"{}\",oid=\"{}\"}} 1",
+            repo_label, ref_label, analysis.head,
+        )
+        .unwrap();
+        Ok((buf, analysis.head))
+    })
+    .await
+    .map_err(|mut response| {
+        // the scrape fails instead of recording the last numbers as current
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        response
+    })?;
+
+    Ok((
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        [("X-Analysed-Tip", tip.to_string())],
+        rendered,
+    )
+        .into_response())
+}
+
+fn gauge(buf: &mut String, name: &str, help: &str, repo_label: &str, value: u64) {
+    writeln!(
+        buf,
+        "# HELP {name} {help}\n# TYPE {name} gauge\n{name}{{repo=\"{repo_label}\"}} {value}"
+    )
+    .unwrap();
+}
+
+/// Escapes a label value, which can't have raw backslashes, quotes or newlines
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
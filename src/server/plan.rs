@@ -0,0 +1,39 @@
+//! What analysing a repository would take, to decide on the limits before pointing the server at it
+
+use super::{analysis_failure, run_blocking, AnalysisKey, AppState, Params};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use unsafe_track::analysis::{self, AnalysisPlan};
+use unsafe_track::github::GithubRepo;
+
+pub async fn plan(
+    State(state): State<AppState>,
+    Path((owner, repo)): Path<(String, String)>,
+    Params(params): Params,
+) -> Result<Json<AnalysisPlan>, Response> {
+    let repo = GithubRepo::parse(&owner, &repo)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()).into_response())?;
+    let key = AnalysisKey::new(&repo, &params);
+    params
+        .check_commit()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    key.check_regexes()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+
+    let failure_key = key.clone();
+    run_blocking(state, &failure_key, move |state, should_interrupt| {
+        analysis::plan_repo(
+            &state.blob_analysis_cache,
+            &state.repo_url(&repo),
+            &key.ref_name,
+            key.path_filter(),
+            &state.limits,
+            should_interrupt,
+        )
+        .map_err(|e| analysis_failure(state, &key, e))
+    })
+    .await
+    .map(Json)
+}
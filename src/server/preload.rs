@@ -0,0 +1,120 @@
+//! Analysing the configured repositories in the background when the server starts
+
+use super::{analyse, AnalysisKey, AppState, GithubParams};
+use crate::config::Config;
+use axum::extract::State;
+use axum::http::Uri;
+use axum::Json;
+use serde::Serialize;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, info_span, warn, Span};
+use unsafe_track::github::GithubRepo;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PreloadState {
+    Pending,
+    Running,
+    Done { commits: usize, seconds: f64 },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PreloadEntry {
+    /// As given on the command line
+    repo: String,
+    #[serde(flatten)]
+    state: PreloadState,
+}
+
+/// The warm-up state of every preloaded repository, in the order they were given
+#[derive(Clone, Default)]
+pub struct PreloadStatus(Arc<Mutex<Vec<PreloadEntry>>>);
+
+impl PreloadStatus {
+    fn set(&self, index: usize, state: PreloadState) {
+        self.0.lock().unwrap()[index].state = state;
+    }
+}
+
+/// Parses `owner/repo`, optionally followed by the query string of the chart
+fn parse_spec(spec: &str, presets: &Config) -> Result<(GithubRepo, AnalysisKey), String> {
+    let (repo, query) = spec.split_once('?').unwrap_or((spec, ""));
+    let Some((owner, repo)) = repo.split_once('/') else {
+        return Err("expected owner/repo".to_string());
+    };
+    let repo = GithubRepo::parse(owner, repo).map_err(|e| e.to_string())?;
+    let uri: Uri = format!("/?{}", query)
+        .parse()
+        .map_err(|e| format!("malformed query: {}", e))?;
+    let params = GithubParams::from_uri(&uri, presets)?;
+
+    params.check_commit()?;
+    let key = AnalysisKey::new(&repo, &params);
+    key.check_regexes().map_err(|e| e.to_string())?;
+    Ok((repo, key))
+}
+
+/// Analyses the `specs` one by one on a blocking thread, filling the caches
+pub fn spawn(state: AppState, specs: Vec<String>) {
+    if specs.is_empty() {
+        return;
+    }
+
+    *state.preload_status.0.lock().unwrap() = specs
+        .iter()
+        .map(|spec| PreloadEntry {
+            repo: spec.clone(),
+            state: PreloadState::Pending,
+        })
+        .collect();
+
+    let span = Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _span = info_span!(parent: &span, "preload").entered();
+
+        // one at a time, not to starve the requests coming in meanwhile
+        let should_interrupt = AtomicBool::new(false);
+        for (index, spec) in specs.iter().enumerate() {
+            let (repo, key) = match parse_spec(spec, &state.presets) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    warn!("Not preloading {}: {}", spec, error);
+                    state
+                        .preload_status
+                        .set(index, PreloadState::Failed { error });
+                    continue;
+                }
+            };
+
+            state.preload_status.set(index, PreloadState::Running);
+            let start = Instant::now();
+            let result = analyse(&state, &repo, key, &should_interrupt);
+            let seconds = start.elapsed().as_secs_f64();
+            match result {
+                Ok(analysis) => {
+                    info!("Preloaded {} in {:.1}s", spec, seconds);
+                    state.preload_status.set(
+                        index,
+                        PreloadState::Done {
+                            commits: analysis.commits.len(),
+                            seconds,
+                        },
+                    );
+                }
+                Err((_, error)) => {
+                    warn!("Preloading {} failed: {}", spec, error);
+                    state
+                        .preload_status
+                        .set(index, PreloadState::Failed { error });
+                }
+            }
+        }
+    });
+}
+
+pub async fn status(State(state): State<AppState>) -> Json<Vec<PreloadEntry>> {
+    Json(state.preload_status.0.lock().unwrap().clone())
+}
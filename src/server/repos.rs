@@ -0,0 +1,69 @@
+//! The listing of the analyses the server holds cached results of
+
+use super::{AppState, ResultKey};
+use axum::extract::State;
+use axum::Json;
+use chrono::{DateTime, SecondsFormat, Utc};
+use moka::notification::RemovalCause;
+use serde::{Serialize, Serializer};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AnalysedRepo {
+    url: String,
+    #[serde(rename = "ref")]
+    ref_name: String,
+    tip_oid: String,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    last_analysed_at: DateTime<Utc>,
+    commit_count: usize,
+}
+
+fn serialize_rfc3339<S: Serializer>(
+    date: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// What is known about the results in [`AppState::results`], kept in step with it
+#[derive(Clone, Default)]
+pub struct AnalysedRepos(Arc<Mutex<HashMap<ResultKey, AnalysedRepo>>>);
+
+impl AnalysedRepos {
+    /// Records a result that was just analysed and cached
+    pub fn insert(&self, key: &ResultKey, url: String, commit_count: usize) {
+        let repo = AnalysedRepo {
+            url,
+            ref_name: key.analysis.ref_name.clone(),
+            tip_oid: key.tip.to_string(),
+            last_analysed_at: Utc::now(),
+            commit_count,
+        };
+        self.0.lock().unwrap().insert(key.clone(), repo);
+    }
+
+    /// To be called when the result is gone from the cache
+    pub fn on_removal(&self, key: &ResultKey, cause: RemovalCause) {
+        // the new result is recorded by `insert`
+        if cause != RemovalCause::Replaced {
+            self.0.lock().unwrap().remove(key);
+        }
+    }
+}
+
+/// The cached results, the most recently analysed first
+pub async fn list(State(state): State<AppState>) -> Json<Vec<AnalysedRepo>> {
+    let mut repos = state
+        .analysed_repos
+        .0
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    repos.sort_by_key(|repo| Reverse(repo.last_analysed_at));
+    Json(repos)
+}
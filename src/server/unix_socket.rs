@@ -0,0 +1,50 @@
+//! Serving over a unix domain socket instead of a TCP port, for a reverse proxy on the same host
+
+use super::connections::{ConnectionLimits, ConnectionStats};
+use axum::Router;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::UnixListener;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+/// A bound socket, its file is removed once [`UnixSocket::serve`] returns
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocket {
+    /// Binds the socket at `path`, replacing the one left behind by a server that didn't shut down
+    pub fn bind(path: PathBuf) -> std::io::Result<Self> {
+        // anything else at the path is kept, binding fails on it
+        if std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Serves `app` until the process gets SIGINT or SIGTERM
+    pub async fn serve(self, app: Router, limits: ConnectionLimits, stats: Arc<ConnectionStats>) {
+        super::connections::serve(&self.listener, app, limits, stats, shutdown_signal()).await;
+
+        info!("Shutting down, removing {}", self.path.display());
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen to SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
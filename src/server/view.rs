@@ -0,0 +1,197 @@
+//! The HTML page wrapping the chart with controls for its parameters
+
+use super::{default_path_filter, Params};
+use axum::extract::{Path, RawQuery};
+use axum::http::StatusCode;
+use axum::response::Html;
+use clap::ValueEnum;
+use serde::Serialize;
+use unsafe_track::github::GithubRepo;
+use unsafe_track::plot::{DateTimezone, MAX_SMOOTHING_WINDOW};
+
+// reloads the chart whenever a control changes, keeping the page URL in sync
+const SCRIPT: &str = r#"
+const form = document.getElementById("controls");
+const chart = document.getElementById("chart");
+form.addEventListener("change", () => {
+    const params = new URLSearchParams();
+    for (const element of form.elements) {
+        if (!element.name) continue;
+        if (element.type === "checkbox") {
+            if (element.checked !== (element.dataset.default === "true")) params.set(element.name, element.checked);
+        } else if (element.type === "select-multiple") {
+            const values = Array.from(element.selectedOptions, (option) => option.value);
+            if (values.length) params.set(element.name, values.join(","));
+        } else if (element.value !== "") {
+            params.set(element.name, element.value);
+        }
+    }
+    const query = params.toString();
+    chart.src = form.dataset.chart + (query ? "?" + query : "");
+    history.replaceState(null, "", query ? "?" + query : location.pathname);
+});
+"#;
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The name of the variant in the query string, which is how serde spells it
+fn query_value<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_owned))
+        .expect("BUG: enum variants serialize to strings")
+}
+
+/// `<option>`s for every variant of the enum, with `current` selected
+pub fn enum_options<T: ValueEnum + Serialize>(current: &T) -> String {
+    enum_multi_options(std::slice::from_ref(current))
+}
+
+/// `<option>`s for every variant of the enum, with all of `current` selected
+pub fn enum_multi_options<T: ValueEnum + Serialize>(current: &[T]) -> String {
+    let current = current.iter().map(query_value).collect::<Vec<_>>();
+    T::value_variants()
+        .iter()
+        .map(|variant| {
+            let value = query_value(variant);
+            let label = variant
+                .to_possible_value()
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_else(|| value.clone());
+            let selected = if current.contains(&value) {
+                " selected"
+            } else {
+                ""
+            };
+            format!(r#"<option value="{value}"{selected}>{label}</option>"#)
+        })
+        .collect()
+}
+
+pub async fn view(
+    Path((owner, repo)): Path<(String, String)>,
+    Params(params): Params,
+    RawQuery(query): RawQuery,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let repo =
+        GithubRepo::parse(&owner, &repo).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let chart = format!(
+        "/github/{}/{}",
+        urlencode_path(&repo.owner),
+        urlencode_path(&repo.name)
+    );
+    let chart_src = match &query {
+        Some(query) => format!("{}?{}", chart, query),
+        None => chart.clone(),
+    };
+    let text = |value: &Option<String>| escape_html(value.as_deref().unwrap_or(""));
+    let checked = |value: bool| if value { " checked" } else { "" };
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>unsafe usage in {title}</title>
+</head>
+<body>
+<h1>unsafe usage in {title}</h1>
+<form id="controls" data-chart="{chart}" onsubmit="return false">
+<label>Ref <input name="ref" placeholder="HEAD" value="{ref_name}"></label>
+<label>Commit <input name="commit" placeholder="full id, instead of the ref" size="40" value="{commit}"></label>
+<label>Compare with <input name="compare_ref" placeholder="other refs, comma-separated" value="{compare_ref}"></label>
+<label>Path filter <input name="path_filter" placeholder="{default_filter}" value="{path_filter}"></label>
+<label>Exclude <input name="exclude" value="{exclude}"></label>
+<label><input type="checkbox" name="ignore_file" data-default="true"{ignore_file}> Use the ignore file</label>
+<label><input type="checkbox" name="skip_vendored" data-default="false"{skip_vendored}> Skip vendored code</label>
+<br>
+<label>Subdirectory <input name="subdir" placeholder="rust/agent" value="{subdir}"></label>
+<label><input type="checkbox" name="trim_empty" data-default="true"{trim_empty}> Trim the commits before it existed</label>
+<label><input type="checkbox" name="expand_local_macros" data-default="false"{expand_local_macros}> Expand local macros (approximate)</label>
+<br>
+<label>X <select name="x_coord">{x_coord}</select></label>
+<label>Y <select name="y_coord" multiple size="3">{y_coord}</select></label>
+<label>Counters of the first Y <select name="count_kinds" multiple size="3">{count_kinds}</select></label>
+<label><input type="checkbox" name="subplots" data-default="false"{subplots}> Chart each on its own</label>
+<label>Mode <select name="mode">{mode}</select></label>
+<label>Palette <select name="palette">{palette}</select></label>
+<label>Timezone <input name="tz" placeholder="utc, commit or +02:00" value="{tz}"></label>
+<label>Smooth <input type="number" name="smooth" min="1" max="{max_smooth}" value="{smooth}"></label>
+<label><input type="checkbox" name="smooth_only" data-default="false"{smooth_only}> Only the average</label>
+<label><input type="checkbox" name="unsafe_only" data-default="false"{unsafe_only}> Only unsafe</label>
+<label><input type="checkbox" name="ratio_axis" data-default="false"{ratio_axis}> Unsafe share axis</label>
+<label><input type="checkbox" name="show_failures" data-default="true"{show_failures}> Failed files</label>
+<label><input type="checkbox" name="markers" data-default="false"{markers}> Commit markers</label>
+<label><input type="checkbox" name="step" data-default="false"{step}> Steps</label>
+<label><input type="checkbox" name="total" data-default="false"{total}> Total</label>
+<label><input type="checkbox" name="caption" data-default="true"{caption}> Caption</label>
+<label>Threshold <input type="number" name="threshold" value="{threshold}"></label>
+<label>Share threshold, % <input type="number" name="ratio_threshold" min="0" max="100" step="any" value="{ratio_threshold}"></label>
+</form>
+<img id="chart" src="{chart_src}" alt="unsafe usage chart">
+<script>{script}</script>
+</body>
+</html>
+"#,
+        title = escape_html(&repo.to_string()),
+        chart = escape_html(&chart),
+        chart_src = escape_html(&chart_src),
+        ref_name = text(&params.ref_name),
+        commit = text(&params.commit),
+        compare_ref = escape_html(&params.compare_ref.join(",")),
+        path_filter = text(&params.path_filter),
+        exclude = text(&params.exclude),
+        ignore_file = checked(params.ignore_file.unwrap_or(true)),
+        skip_vendored = checked(params.skip_vendored),
+        default_filter = escape_html(default_path_filter()),
+        subdir = text(&params.subdir),
+        trim_empty = checked(params.trim_empty.unwrap_or(true)),
+        expand_local_macros = checked(params.expand_local_macros),
+        x_coord = enum_options(&params.x_coord),
+        count_kinds = enum_multi_options(&params.count_kinds),
+        y_coord = enum_multi_options(&params.y_coord),
+        subplots = checked(params.subplots),
+        unsafe_only = checked(params.unsafe_only),
+        mode = enum_options(&params.mode),
+        palette = enum_options(&params.palette),
+        tz = if params.tz == DateTimezone::default() {
+            String::new()
+        } else {
+            escape_html(&params.tz.to_string())
+        },
+        max_smooth = MAX_SMOOTHING_WINDOW,
+        smooth = params.smooth.map(|s| s.to_string()).unwrap_or_default(),
+        smooth_only = checked(params.smooth_only),
+        ratio_axis = checked(params.ratio_axis),
+        show_failures = checked(params.show_failures.unwrap_or(true)),
+        markers = checked(params.markers),
+        step = checked(params.step),
+        total = checked(params.total),
+        caption = checked(params.caption.unwrap_or(true)),
+        threshold = params.threshold.map(|t| t.to_string()).unwrap_or_default(),
+        ratio_threshold = params
+            .ratio_threshold
+            .map(|t| t.to_string())
+            .unwrap_or_default(),
+        script = SCRIPT,
+    )))
+}
+
+/// Percent-encodes everything but the unreserved characters of a path segment
+fn urlencode_path(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}